@@ -1,31 +1,473 @@
+use crate::responses::AccountRecord;
 use crate::types::*;
 
+/// Accounts of users who logged in, keyed by the user string their principal is seeded on.
+///
+/// Telegram users are seeded on their numeric id in canonical decimal form. Accounts created
+/// before that from other strings, like `@alice`, are legacy accounts: they can be aliased to a
+/// Telegram id so the user keeps their principal. Usernames are mutable metadata used for
+/// lookups only.
+///
+/// Once a deployment salt is generated, new accounts are seeded on the salt and the user
+/// string. Accounts that existed at that time are flagged as unsalted and keep their principal.
+///
+/// A user can have named profiles, each with its own principal, and applications and isolated
+/// bots give their users principals of their own. Each such
+/// `Scope` is registered under its user string and seeded on its fields, never on that string.
 pub struct Accounts {
     users: StableBTreeMap<Principal, Hash, VM>,
     principals: StableBTreeMap<Hash, Principal, VM>,
     seeds: StableBTreeMap<Hash, String, VM>,
+    usernames: StableBTreeMap<String, u64, VM>,
+    names: StableBTreeMap<u64, String, VM>,
+    aliases: StableBTreeMap<u64, String, VM>,
+    unsalted: StableBTreeMap<Hash, (), VM>,
+    salt: StableCell<Vec<u8>, VM>,
+    links: StableBTreeMap<Principal, String, VM>,
+    nonces: StableBTreeMap<Hash, LinkNonce, VM>,
+    scopes: StableBTreeMap<String, Scope, VM>,
+    nonce_expirations: StableBTreeMap<(u64, Hash), (), VM>,
+    legacies: StableBTreeMap<String, u64, VM>,
+    user_links: StableBTreeMap<(Hash, Principal), (), VM>,
+}
+
+/// Pending link of a second principal to a user, issued by `linkNonce`.
+#[derive(Clone, Deserialize, Serialize)]
+struct LinkNonce {
+    user: String,
+    expiration: u64,
+}
+
+impl Storable for LinkNonce {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        serde_cbor::to_vec(self).unwrap().into()
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        serde_cbor::from_slice(&bytes).unwrap()
+    }
+}
+
+/// What `erase` removed for a user, with the seed hashes and user strings to purge elsewhere.
+pub(crate) struct Erasure {
+    pub users: Vec<String>,
+    pub seeds: Vec<Hash>,
+    pub principals: Vec<Principal>,
+    pub links: Vec<Principal>,
+    pub username: bool,
+    pub alias: bool,
+}
+
+/// Lowercase username without the leading `@`, as Telegram usernames are case-insensitive.
+fn normalize(username: &str) -> String {
+    username.trim().trim_start_matches('@').to_lowercase()
+}
+
+/// Maximum length of a profile name, in characters.
+pub(crate) const PROFILE_LENGTH: usize = 32;
+
+/// Whether a profile name or application id is short and only uses lowercase letters, digits,
+/// `-` and `_`.
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= PROFILE_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+pub(crate) fn validate_profile(profile: &str) -> Result<(), String> {
+    match valid_name(profile) {
+        true => Ok(()),
+        false => Err("Profile name is invalid".to_owned()),
+    }
+}
+
+pub(crate) fn validate_application(id: &str) -> Result<(), String> {
+    match valid_name(id) {
+        true => Ok(()),
+        false => Err("Application id is invalid".to_owned()),
+    }
+}
+
+/// Telegram id of a user string in canonical decimal form.
+pub(crate) fn telegram_id(user: &str) -> Option<u64> {
+    user.parse::<u64>().ok().filter(|id| id.to_string() == user)
 }
 
 impl Accounts {
-    fn new(memories: [VM; 3]) -> Self {
-        let [m1, m2, m3] = memories;
+    fn new(memories: [VM; 14]) -> Self {
+        let [m1, m2, m3, m4, m5, m6, m7, m8, m9, m10, m11, m12, m13, m14] = memories;
 
         Self {
             users: StableBTreeMap::init(m1),
             principals: StableBTreeMap::init(m2),
             seeds: StableBTreeMap::init(m3),
+            usernames: StableBTreeMap::init(m4),
+            names: StableBTreeMap::init(m5),
+            aliases: StableBTreeMap::init(m6),
+            unsalted: StableBTreeMap::init(m7),
+            salt: StableCell::init(m8, Vec::new()).unwrap(),
+            links: StableBTreeMap::init(m9),
+            nonces: StableBTreeMap::init(m10),
+            scopes: StableBTreeMap::init(m11),
+            nonce_expirations: StableBTreeMap::init(m12),
+            legacies: StableBTreeMap::init(m13),
+            user_links: StableBTreeMap::init(m14),
         }
     }
 
-    pub(crate) fn init(memories: [VM; 3]) -> RefCell<Self> {
+    pub(crate) fn init(memories: [VM; 14]) -> RefCell<Self> {
         RefCell::new(Self::new(memories))
     }
 
+    /// Indexes the aliases and linked principals stored before their reverse indexes existed,
+    /// and returns how many were indexed.
+    pub(crate) fn restore(&mut self) -> usize {
+        let mut indexed = 0;
+
+        if self.legacies.is_empty() {
+            for (id, legacy) in self.aliases.iter() {
+                self.legacies.insert(legacy, id);
+                indexed += 1;
+            }
+        }
+
+        if self.user_links.is_empty() {
+            for (principal, user) in self.links.iter() {
+                self.user_links.insert((hash::string(&user), principal), ());
+                indexed += 1;
+            }
+        }
+
+        indexed
+    }
+
+    /// Records a link nonce for a user, keyed by its hash.
+    pub(crate) fn insert_nonce(&mut self, nonce: &[u8], user: &str, expiration: u64) {
+        let hash = hash::bytes(nonce);
+        let user = user.to_owned();
+
+        self.nonces.insert(hash, LinkNonce { user, expiration });
+        self.nonce_expirations.insert((expiration, hash), ());
+    }
+
+    fn remove_nonce(&mut self, hash: &Hash) -> Option<LinkNonce> {
+        let nonce = self.nonces.remove(hash)?;
+
+        self.nonce_expirations.remove(&(nonce.expiration, *hash));
+
+        Some(nonce)
+    }
+
+    /// Consumes a link nonce and returns the user it was issued for, unless it expired.
+    pub(crate) fn take_nonce(&mut self, nonce: &[u8]) -> Option<String> {
+        self.remove_nonce(&hash::bytes(nonce))
+            .filter(|nonce| nonce.expiration > utils::now())
+            .map(|nonce| nonce.user)
+    }
+
+    /// Removes the link nonces expired at `now` and returns how many were removed.
+    pub(crate) fn prune_nonces(&mut self, now: u64) -> usize {
+        let expired = self
+            .nonce_expirations
+            .iter()
+            .take_while(|((expiration, _), _)| *expiration <= now)
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
+
+        for key in &expired {
+            self.nonce_expirations.remove(key);
+            self.nonces.remove(&key.1);
+        }
+
+        expired.len()
+    }
+
+    /// Links a second principal to a user. A principal can be linked to one user only, and
+    /// cannot be the principal of an account itself.
+    pub(crate) fn link(&mut self, principal: Principal, user: &str) -> Result<(), String> {
+        if principal == Principal::anonymous() {
+            return Err("Anonymous principal cannot be linked".to_owned());
+        }
+
+        if self.users.contains_key(&principal) {
+            return Err("Principal belongs to an account".to_owned());
+        }
+
+        if let Some(linked) = self.links.get(&principal) {
+            if linked != user {
+                return Err("Principal is already linked to another user".to_owned());
+            }
+        }
+
+        self.insert_link(principal, user);
+
+        Ok(())
+    }
+
+    fn insert_link(&mut self, principal: Principal, user: &str) {
+        self.links.insert(principal, user.to_owned());
+        self.user_links.insert((hash::string(user), principal), ());
+    }
+
+    pub(crate) fn unlink(&mut self, principal: &Principal) -> Option<String> {
+        let user = self.links.remove(principal)?;
+
+        self.user_links.remove(&(hash::string(&user), *principal));
+
+        Some(user)
+    }
+
+    /// Principals linked to a user.
+    pub(crate) fn linked(&self, user: &str) -> Vec<Principal> {
+        let seed = hash::string(user);
+
+        self.user_links
+            .range((seed, Principal::management_canister())..)
+            .take_while(|((linked, _), _)| *linked == seed)
+            .map(|((_, principal), _)| principal)
+            .collect()
+    }
+
+    /// User of an account principal or of a linked principal.
+    pub(crate) fn owner(&self, principal: &Principal) -> Option<String> {
+        self.user(principal).or_else(|| self.links.get(principal))
+    }
+
+    pub(crate) fn salt(&self) -> Vec<u8> {
+        self.salt.get().clone()
+    }
+
+    /// Whether a deployment salt has been generated.
+    pub(crate) fn has_salt(&self) -> bool {
+        !self.salt.get().is_empty()
+    }
+
+    /// Sets the deployment salt, once, and flags every existing account as unsalted.
+    pub(crate) fn set_salt(&mut self, salt: Vec<u8>) -> Result<usize, String> {
+        if self.has_salt() {
+            return Err("Salt is already set".to_owned());
+        }
+
+        if salt.is_empty() {
+            return Err("Salt is empty".to_owned());
+        }
+
+        let seeds = self.seeds.iter().map(|(seed, _)| seed).collect::<Vec<_>>();
+
+        for seed in &seeds {
+            self.unsalted.insert(*seed, ());
+        }
+
+        self.salt.set(salt).map_err(|e| format!("{:?}", e))?;
+
+        Ok(seeds.len())
+    }
+
+    /// Whether the principal of a user is derived with the deployment salt.
+    pub(crate) fn salted(&self, user: &str) -> bool {
+        self.has_salt() && !self.unsalted.contains_key(&hash::string(user))
+    }
+
+    /// Seed of the principal of a user, salted unless no salt is set or the account is legacy.
+    /// Registered scopes are seeded on the scope.
+    pub(crate) fn seed_of(&self, user: &str) -> Hash {
+        if let Some(scope) = self.scopes.get(&user.to_owned()) {
+            return self.scope_seed(&scope);
+        }
+
+        match self.salted(user) {
+            true => utils::salted_seed(self.salt.get(), user),
+            false => utils::seed(user),
+        }
+    }
+
+    /// Seed of the principal of a scope, whether it is registered yet or not.
+    pub(crate) fn scope_seed(&self, scope: &Scope) -> Hash {
+        let salt = self
+            .salted(&scope.name())
+            .then(|| self.salt.get().as_slice());
+
+        scope.seed(salt)
+    }
+
+    /// Registers a scope under its user string and returns that string. Fails if the string is
+    /// the user of an account seeded on it, or of another scope.
+    pub(crate) fn register(&mut self, scope: &Scope) -> Result<String, String> {
+        let user = scope.name();
+
+        match self.scopes.get(&user) {
+            Some(registered) if registered == *scope => Ok(user),
+            Some(_) => Err(format!("User {user} belongs to another scope")),
+            None if self.seeds.contains_key(&hash::string(&user)) => {
+                Err(format!("User {user} belongs to an existing account"))
+            }
+            None => {
+                self.scopes.insert(user.clone(), scope.clone());
+
+                Ok(user)
+            }
+        }
+    }
+
+    /// Scope a user string is registered for, if it is not a default principal.
+    pub(crate) fn scope(&self, user: &str) -> Option<Scope> {
+        self.scopes.get(&user.to_owned())
+    }
+
+    /// Registered scopes of a user, by user string.
+    fn scopes_of(&self, user: &str) -> Vec<(String, Scope)> {
+        let prefix = format!("{user}?");
+
+        self.scopes
+            .range(prefix.clone()..)
+            .take_while(|(name, _)| name.starts_with(&prefix))
+            .filter(|(_, scope)| scope.user() == user)
+            .collect()
+    }
+
+    pub(crate) fn seed(user: &str) -> Hash {
+        Self::borrow(|accounts| accounts.seed_of(user))
+    }
+
+    /// User string the principal of a Telegram user is seeded on: its legacy alias, if any,
+    /// otherwise its id.
+    pub(crate) fn seed_user(&self, id: u64) -> String {
+        self.aliases.get(&id).unwrap_or_else(|| id.to_string())
+    }
+
+    /// Resolves a Telegram id, a username with or without `@`, or a legacy user string to the
+    /// user string its principal is seeded on.
+    pub(crate) fn resolve(&self, user: &str) -> Option<String> {
+        let user = user.trim();
+
+        if let Some(id) = telegram_id(user) {
+            return Some(self.seed_user(id));
+        }
+
+        if !user.starts_with('@') && self.principals.contains_key(&hash::string(user)) {
+            return Some(user.to_owned());
+        }
+
+        self.usernames
+            .get(&normalize(user))
+            .map(|id| self.seed_user(id))
+    }
+
+    /// Records the current username of a Telegram user, releasing the one it had before and
+    /// taking it over from any user who had it.
+    pub(crate) fn set_username(&mut self, id: u64, username: Option<&str>) {
+        let username = username.map(normalize).filter(|name| !name.is_empty());
+
+        if let Some(previous) = self.names.get(&id) {
+            if Some(&previous) == username.as_ref() {
+                return;
+            }
+
+            self.names.remove(&id);
+            self.usernames.remove(&previous);
+        }
+
+        if let Some(username) = username {
+            if let Some(other) = self.usernames.insert(username.clone(), id) {
+                self.names.remove(&other);
+            }
+
+            self.names.insert(id, username);
+        }
+    }
+
+    pub(crate) fn username(&self, id: u64) -> Option<String> {
+        self.names.get(&id)
+    }
+
+    /// Aliases a legacy account to a Telegram id, so the user keeps the legacy principal when
+    /// logging in with Telegram.
+    pub(crate) fn alias(&mut self, legacy: &str, id: u64) -> Result<Principal, String> {
+        if telegram_id(legacy).is_some() {
+            return Err(format!("User {legacy} is already a Telegram id"));
+        }
+
+        let principal = self
+            .principal(legacy)
+            .ok_or(format!("Legacy user {legacy} not found"))?;
+
+        if self.principals.contains_key(&hash::string(&id.to_string())) {
+            return Err(format!("Telegram user {id} already has an account"));
+        }
+
+        if let Some(other) = self
+            .legacies
+            .get(&legacy.to_owned())
+            .filter(|other| *other != id)
+        {
+            return Err(format!("Legacy user {legacy} is aliased to {other}"));
+        }
+
+        self.insert_alias(id, legacy);
+
+        Ok(principal)
+    }
+
+    fn insert_alias(&mut self, id: u64, legacy: &str) {
+        if let Some(previous) = self.aliases.insert(id, legacy.to_owned()) {
+            self.legacies.remove(&previous);
+        }
+
+        self.legacies.insert(legacy.to_owned(), id);
+    }
+
+    fn remove_alias(&mut self, id: u64) -> Option<String> {
+        let legacy = self.aliases.remove(&id)?;
+
+        self.legacies.remove(&legacy);
+
+        Some(legacy)
+    }
+
+    /// User strings of a user: the user itself and every scope registered for it.
+    pub(crate) fn owned(&self, user: &str) -> Vec<String> {
+        let mut users = vec![user.to_owned()];
+
+        users.extend(self.scopes_of(user).into_iter().map(|(name, _)| name));
+        users
+    }
+
+    /// Hashes of the seeds of every principal of a user, which key its delegations.
+    pub(crate) fn seed_hashes(&self, user: &str) -> Vec<Hash> {
+        self.owned(user)
+            .iter()
+            .map(|user| hash::bytes(self.seed_of(user)))
+            .collect()
+    }
+
+    /// Principals of the profiles and applications a user logged in with, by user string.
+    pub(crate) fn profiles(&self, user: &str) -> Map<String, Principal> {
+        self.scopes_of(user)
+            .into_iter()
+            .filter_map(|(name, _)| Some((name.clone(), self.principal(&name)?)))
+            .collect()
+    }
+
+    /// Telegram id a user string belongs to, directly or through an alias. Scopes belong to
+    /// the Telegram id of their user.
+    pub(crate) fn id(&self, user: &str) -> Option<u64> {
+        let user = self
+            .scope(user)
+            .map_or(user.to_owned(), |scope| scope.user().to_owned());
+
+        telegram_id(&user).or_else(|| self.legacies.get(&user))
+    }
+
     pub(crate) fn store(&mut self, user: &str, principal: &Principal) {
         let seed = hash::string(user);
-        let user = user.to_owned();
         let principal = *principal;
 
+        let user = user.to_owned();
+
         self.users.insert(principal, seed);
         self.principals.insert(seed, principal);
         self.seeds.insert(seed, user);
@@ -41,6 +483,19 @@ impl Accounts {
         self.principals.get(&hash::string(user))
     }
 
+    /// Principal of a Telegram id, a username or a legacy user string. A linked principal, in
+    /// text form, resolves to the principal of the account it is linked to.
+    pub(crate) fn find(&self, user: &str) -> Option<Principal> {
+        if let Some(linked) = Principal::from_text(user.trim())
+            .ok()
+            .and_then(|principal| self.links.get(&principal))
+        {
+            return self.principal(&linked);
+        }
+
+        self.principal(&self.resolve(user)?)
+    }
+
     pub(crate) fn all(&self) -> impl Iterator<Item = (String, Principal)> {
         let users = self.users.iter().collect::<Map<_, _>>();
         let seeds = self.seeds.iter().collect::<Map<_, _>>();
@@ -55,7 +510,282 @@ impl Accounts {
         map.into_iter()
     }
 
-    #[allow(unused)]
+    /// Removes every record of a user: the accounts of the user, its profiles and application
+    /// principals, linked principals, link nonces, username and alias.
+    pub(crate) fn erase(&mut self, user: &str) -> Erasure {
+        let users = self.owned(user);
+        let seeds = self.seed_hashes(user);
+        let id = self.id(user);
+        let mut principals = Vec::new();
+
+        for user in &users {
+            let seed = hash::string(user);
+
+            if let Some(principal) = self.principals.remove(&seed) {
+                self.users.remove(&principal);
+                principals.push(principal);
+            }
+
+            self.seeds.remove(&seed);
+            self.unsalted.remove(&seed);
+            self.scopes.remove(user);
+        }
+
+        let links = users
+            .iter()
+            .flat_map(|user| self.linked(user))
+            .collect::<Vec<_>>();
+
+        for principal in &links {
+            self.unlink(principal);
+        }
+
+        let nonces = self
+            .nonces
+            .iter()
+            .filter(|(_, nonce)| users.contains(&nonce.user))
+            .map(|(hash, _)| hash)
+            .collect::<Vec<_>>();
+
+        for hash in nonces {
+            self.remove_nonce(&hash);
+        }
+
+        let username = id.and_then(|id| self.names.remove(&id));
+
+        if let Some(username) = &username {
+            self.usernames.remove(username);
+        }
+
+        let alias = id.and_then(|id| self.remove_alias(id));
+
+        Erasure {
+            users,
+            seeds,
+            principals,
+            links,
+            username: username.is_some(),
+            alias: alias.is_some(),
+        }
+    }
+
+    /// Accounts in seed hash order, starting at `start`, with the seed hash of the next page.
+    pub(crate) fn export(
+        &self,
+        start: Option<Hash>,
+        limit: usize,
+    ) -> (Vec<AccountRecord>, Option<Hash>) {
+        let mut seeds = self.seeds.range(start.unwrap_or_default()..);
+        let records = seeds
+            .by_ref()
+            .take(limit)
+            .filter_map(|(seed, user)| {
+                let principal = self.principals.get(&seed)?;
+
+                Some(AccountRecord {
+                    principal,
+                    salted: self.salted(&user),
+                    alias: self.legacies.get(&user),
+                    username: self.id(&user).and_then(|id| self.username(id)),
+                    links: self.linked(&user),
+                    scope: self.scope(&user),
+                    user,
+                })
+            })
+            .collect();
+
+        (records, seeds.next().map(|(seed, _)| seed))
+    }
+
+    /// Imports accounts exported from another SIWT canister, with its salt. The principal of
+    /// every account must match the one derived here, so the signing canister must be set to
+    /// the exporting canister, or to its own signing canister, first.
+    ///
+    /// The whole page is validated before anything is written, so it is imported entirely or
+    /// not at all.
+    pub(crate) fn import(
+        &mut self,
+        canister: &Principal,
+        records: Vec<AccountRecord>,
+        salt: Option<Vec<u8>>,
+    ) -> Result<u64, String> {
+        if let Some(salt) = &salt {
+            if self.has_salt() && self.salt.get() != salt {
+                return Err("Salt differs from the salt of this canister".to_owned());
+            }
+        }
+
+        let salt = salt.unwrap_or_else(|| self.salt.get().clone());
+
+        self.validate_import(canister, &records, &salt)?;
+
+        if !self.has_salt() && !salt.is_empty() {
+            self.set_salt(salt)?;
+        }
+
+        for record in &records {
+            if !record.salted {
+                self.unsalted.insert(hash::string(&record.user), ());
+            }
+
+            if let Some(scope) = &record.scope {
+                self.scopes.insert(record.user.clone(), scope.clone());
+            }
+
+            self.store(&record.user, &record.principal);
+
+            if let Some(id) = record.alias {
+                self.insert_alias(id, &record.user);
+            }
+
+            if let Some(id) = self.id(&record.user) {
+                self.set_username(id, record.username.as_deref());
+            }
+
+            for principal in &record.links {
+                self.insert_link(*principal, &record.user);
+            }
+        }
+
+        Ok(records.len() as u64)
+    }
+
+    /// Checks a page of imported accounts against this canister and against itself: users,
+    /// principals, aliases and linked principals must each appear once, and must not belong to
+    /// another account here.
+    fn validate_import(
+        &self,
+        canister: &Principal,
+        records: &[AccountRecord],
+        salt: &[u8],
+    ) -> Result<(), String> {
+        let mut users = Set::new();
+        let mut principals = Set::new();
+        let mut aliases = Set::new();
+        let mut links = Set::new();
+
+        for record in records {
+            if !users.insert(record.user.as_str()) {
+                return Err(format!("User {} is imported twice", record.user));
+            }
+
+            if !principals.insert(record.principal) {
+                return Err(format!(
+                    "Principal {} is imported twice",
+                    record.principal.to_text()
+                ));
+            }
+
+            if record.salted && salt.is_empty() {
+                return Err(format!(
+                    "User {} is salted but no salt is given",
+                    record.user
+                ));
+            }
+
+            if let Some(scope) = &record.scope {
+                if scope.name() != record.user {
+                    return Err(format!("Scope of user {} does not match it", record.user));
+                }
+            }
+
+            let registered = self.scope(&record.user);
+
+            if (registered.is_some() || self.seeds.contains_key(&hash::string(&record.user)))
+                && registered != record.scope
+            {
+                return Err(format!("User {} exists with another scope", record.user));
+            }
+
+            if self
+                .user(&record.principal)
+                .is_some_and(|user| user != record.user)
+                || self.links.contains_key(&record.principal)
+            {
+                return Err(format!(
+                    "Principal of user {} belongs to another account",
+                    record.user
+                ));
+            }
+
+            if self
+                .principal(&record.user)
+                .is_some_and(|principal| principal != record.principal)
+            {
+                return Err(format!(
+                    "User {} exists with another principal",
+                    record.user
+                ));
+            }
+
+            let seed = match (&record.scope, record.salted) {
+                (Some(scope), salted) => scope.seed(salted.then_some(salt)),
+                (None, true) => utils::salted_seed(salt, &record.user),
+                (None, false) => utils::seed(&record.user),
+            };
+            let pubkey = Self::canister_public_key(canister, &seed)?;
+
+            if Principal::self_authenticating(&pubkey) != record.principal {
+                return Err(format!(
+                    "Principal of user {} does not match the signing canister",
+                    record.user
+                ));
+            }
+
+            if let Some(id) = record.alias {
+                let taken = telegram_id(&record.user).is_some()
+                    || record.scope.is_some()
+                    || self.principals.contains_key(&hash::string(&id.to_string()))
+                    || self
+                        .aliases
+                        .get(&id)
+                        .is_some_and(|legacy| legacy != record.user)
+                    || self
+                        .legacies
+                        .get(&record.user)
+                        .is_some_and(|other| other != id);
+
+                if taken || !aliases.insert(id) {
+                    return Err(format!("Alias {id} of user {} is taken", record.user));
+                }
+            }
+
+            for principal in &record.links {
+                let taken = *principal == Principal::anonymous()
+                    || self.users.contains_key(principal)
+                    || self
+                        .links
+                        .get(principal)
+                        .is_some_and(|user| user != record.user);
+
+                if taken || !links.insert(*principal) {
+                    return Err(format!(
+                        "Linked principal {} of user {} is taken",
+                        principal.to_text(),
+                        record.user
+                    ));
+                }
+            }
+        }
+
+        if let Some(principal) = links.intersection(&principals).next() {
+            return Err(format!(
+                "Linked principal {} is the principal of an imported account",
+                principal.to_text()
+            ));
+        }
+
+        for record in records {
+            if let Some(id) = record.alias {
+                if users.contains(id.to_string().as_str()) {
+                    return Err(format!("Alias {id} is an imported account"));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     pub(crate) fn borrow<F: FnOnce(&Self) -> R, R>(f: F) -> R {
         states::accounts::borrow(f)
     }
@@ -64,9 +794,24 @@ impl Accounts {
         states::accounts::borrow_mut(f)
     }
 
+    /// Public key of a user's principal, issued by the signing canister in the settings.
     pub(crate) fn user_canister_public_key(user: &str) -> Result<ByteBuf, String> {
-        let canister = canister_principal().as_slice().to_vec();
-        let seed = utils::seed(user);
+        Self::canister_public_key(&Setting::get().signing_canister(), &Self::seed(user))
+    }
+
+    /// Public key this canister signs a user's delegations with. It differs from the principal
+    /// key once the canister replaced another one, which then delegates from the principal key
+    /// to this one.
+    pub(crate) fn signer_public_key(user: &str) -> Result<ByteBuf, String> {
+        Self::canister_public_key(&canister_principal(), &Self::seed(user))
+    }
+
+    /// DER-encoded canister signature public key of a seed and a signing canister.
+    pub(crate) fn canister_public_key(
+        canister: &Principal,
+        seed: &[u8],
+    ) -> Result<ByteBuf, String> {
+        let canister = canister.as_slice().to_vec();
         let mut bytes = Vec::new();
 
         bytes.push(canister.len() as u8);
@@ -82,3 +827,126 @@ impl Accounts {
         Ok(ByteBuf::from(pubkey))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accounts() -> Accounts {
+        let manager = MemoryManager::init(DefaultMemoryImpl::default());
+
+        Accounts::new(std::array::from_fn(|id| {
+            manager.get(MemoryId::new(id as u8))
+        }))
+    }
+
+    fn record(canister: &Principal, user: &str, links: Vec<Principal>) -> AccountRecord {
+        let pubkey = Accounts::canister_public_key(canister, &utils::seed(user)).unwrap();
+
+        AccountRecord {
+            user: user.to_owned(),
+            principal: Principal::self_authenticating(pubkey),
+            salted: false,
+            alias: None,
+            username: None,
+            links,
+            scope: None,
+        }
+    }
+
+    #[test]
+    fn import_writes_nothing_when_a_page_conflicts_with_itself() {
+        let canister = Principal::from_slice(&[1; 10]);
+        let alice = record(&canister, "@alice", vec![]);
+        let bob = record(&canister, "@bob", vec![alice.principal]);
+        let mut accounts = accounts();
+
+        let error = accounts
+            .import(&canister, vec![alice, bob], None)
+            .unwrap_err();
+
+        assert_eq!(
+            error,
+            format!(
+                "Linked principal {} is the principal of an imported account",
+                record(&canister, "@alice", vec![]).principal.to_text()
+            )
+        );
+        assert_eq!(accounts.all().count(), 0);
+    }
+
+    #[test]
+    fn import_rejects_principals_linked_twice_in_a_page() {
+        let canister = Principal::from_slice(&[1; 10]);
+        let linked = Principal::from_slice(&[2; 29]);
+        let alice = record(&canister, "@alice", vec![linked]);
+        let bob = record(&canister, "@bob", vec![linked]);
+        let mut accounts = accounts();
+
+        assert!(accounts.import(&canister, vec![alice, bob], None).is_err());
+        assert_eq!(accounts.all().count(), 0);
+        assert_eq!(accounts.owner(&linked), None);
+    }
+
+    #[test]
+    fn import_rejects_users_imported_twice() {
+        let canister = Principal::from_slice(&[1; 10]);
+        let alice = record(&canister, "@alice", vec![]);
+        let again = record(&canister, "@alice", vec![]);
+        let mut accounts = accounts();
+
+        assert_eq!(
+            accounts.import(&canister, vec![alice, again], None),
+            Err("User @alice is imported twice".to_owned())
+        );
+        assert_eq!(accounts.all().count(), 0);
+    }
+
+    #[test]
+    fn reverse_indexes_follow_aliases_and_links() {
+        let canister = Principal::from_slice(&[1; 10]);
+        let alice = record(&canister, "@alice", vec![]);
+        let first = Principal::from_slice(&[2; 29]);
+        let second = Principal::from_slice(&[3; 29]);
+        let mut accounts = accounts();
+
+        accounts.store(&alice.user, &alice.principal);
+        accounts.alias("@alice", 42).unwrap();
+        accounts.link(first, "@alice").unwrap();
+        accounts.link(second, "@alice").unwrap();
+        accounts.unlink(&first);
+
+        assert_eq!(accounts.id("@alice"), Some(42));
+        assert_eq!(accounts.linked("@alice"), vec![second]);
+        assert_eq!(
+            accounts.alias("@alice", 43),
+            Err("Legacy user @alice is aliased to 42".to_owned())
+        );
+
+        let (records, _) = accounts.export(None, 10);
+
+        assert_eq!(records[0].alias, Some(42));
+        assert_eq!(records[0].links, vec![second]);
+
+        let erasure = accounts.erase("@alice");
+
+        assert_eq!(erasure.links, vec![second]);
+        assert!(erasure.alias);
+        assert_eq!(accounts.id("@alice"), None);
+        assert!(accounts.linked("@alice").is_empty());
+    }
+
+    #[test]
+    fn restore_indexes_aliases_and_links_stored_before_the_indexes() {
+        let linked = Principal::from_slice(&[2; 29]);
+        let mut accounts = accounts();
+
+        accounts.aliases.insert(42, "@alice".to_owned());
+        accounts.links.insert(linked, "@alice".to_owned());
+
+        assert_eq!(accounts.restore(), 2);
+        assert_eq!(accounts.id("@alice"), Some(42));
+        assert_eq!(accounts.linked("@alice"), vec![linked]);
+        assert_eq!(accounts.restore(), 0);
+    }
+}
@@ -0,0 +1,79 @@
+use crate::types::{CandidType, Deserialize, Principal, Serialize, Set};
+
+/// Maximum length of an origin.
+const ORIGIN_LENGTH: usize = 256;
+
+/// Application whose users get principals of their own, keyed by application id in the
+/// settings.
+///
+/// Every origin of an application derives the same principals, so an application can move to
+/// a new domain or frontend canister by adding its new origin next to the old one. Its origins
+/// can only sign in with its own bots, and to its own canisters.
+#[derive(CandidType, Clone, Deserialize, Serialize)]
+pub struct Application {
+    origins: Set<String>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    bots: Set<u64>,
+    #[serde(default)]
+    canisters: Set<Principal>,
+}
+
+impl Application {
+    /// Derivation origins of the application, e.g. `https://app.example.com`.
+    pub fn origins(&self) -> impl Iterator<Item = &str> {
+        self.origins.iter().map(String::as_str)
+    }
+
+    /// Friendly name shown in consent messages instead of the id.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Bots whose proofs the application's origins may sign in with.
+    pub fn bots(&self) -> impl Iterator<Item = &u64> {
+        self.bots.iter()
+    }
+
+    /// Canisters delegations for the application may target, besides this canister. No other
+    /// application, and no sign-in without an origin, may target them.
+    pub fn canisters(&self) -> impl Iterator<Item = &Principal> {
+        self.canisters.iter()
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.origins.is_empty() {
+            return Err("Application has no origins".to_owned());
+        }
+
+        if self.bots.is_empty() {
+            return Err("Application has no bots".to_owned());
+        }
+
+        for origin in &self.origins {
+            validate_origin(origin)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that an origin is a lowercase `http` or `https` scheme and host, without a path.
+pub(crate) fn validate_origin(origin: &str) -> Result<(), String> {
+    let host = origin
+        .strip_prefix("https://")
+        .or_else(|| origin.strip_prefix("http://"));
+
+    match host {
+        Some(host)
+            if !host.is_empty()
+                && origin.len() <= ORIGIN_LENGTH
+                && origin == origin.to_lowercase()
+                && !host.contains(['/', '?', '#', ' ']) =>
+        {
+            Ok(())
+        }
+        _ => Err(format!("Origin {origin} is invalid")),
+    }
+}
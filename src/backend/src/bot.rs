@@ -0,0 +1,67 @@
+use crate::types::{CandidType, Deserialize, Principal, Serialize, Set, Verification};
+
+/// Policy of one Telegram bot served by the canister, keyed by bot id in the settings.
+#[derive(CandidType, Clone, Deserialize, Serialize)]
+pub struct Bot {
+    token: Option<String>,
+    verification: Verification,
+    canisters: Set<Principal>,
+    expiration_minute: Option<u64>,
+    #[serde(default)]
+    public: bool,
+    #[serde(default)]
+    isolated: bool,
+}
+
+impl Bot {
+    /// Bot token, required for `Token` verification and for Login Widget data.
+    pub fn token(&self) -> Result<&str, String> {
+        self.token
+            .as_deref()
+            .ok_or("Telegram bot token is not configured".to_owned())
+    }
+
+    pub fn verification(&self) -> Verification {
+        self.verification
+    }
+
+    /// Default delegation targets, added to every delegation issued for this bot.
+    pub fn canisters(&self) -> impl Iterator<Item = &Principal> {
+        self.canisters.iter()
+    }
+
+    /// Delegation expiration for this bot, overriding the global one when set.
+    pub fn expiration_minute(&self) -> Option<u64> {
+        self.expiration_minute
+    }
+
+    /// Whether anyone, not only authorized principals, may log in with this bot's proofs.
+    pub fn public(&self) -> bool {
+        self.public
+    }
+
+    /// Whether users of this bot get principals of their own, so the holder of its token cannot
+    /// sign in as a user of another bot. Bots share the canister-wide principal of each user
+    /// unless isolated.
+    pub fn isolated(&self) -> bool {
+        self.isolated
+    }
+
+    pub(crate) fn validate(&self, id: u64) -> Result<(), String> {
+        match self.token.as_deref().map(str::trim) {
+            Some(token) if token.split(':').next() != Some(id.to_string().as_str()) => {
+                Err(format!("Telegram bot token does not belong to bot {id}"))
+            }
+            None if self.verification == Verification::Token => {
+                Err("Telegram bot token is required for token verification".to_owned())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Copy of the bot that is safe to return to callers, with the token removed.
+    pub(crate) fn redacted(mut self) -> Self {
+        self.token = None;
+        self
+    }
+}
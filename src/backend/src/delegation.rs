@@ -1,14 +1,14 @@
 use crate::responses::{Delegated, SignedDelegation};
 use crate::types::{
-    fork, hash, labeled, labeled_hash, utils, Accounts, AsHashTree, ByteBuf, CandidType, Hash,
-    HashMap, HashTree, Principal, Serialize, Set, State, Timestamp,
-    LABEL_ASSETS, LABEL_SIG,
+    fork, hash, labeled, labeled_hash, Accounts, AsHashTree, ByteBuf, CandidType, Hash, HashMap,
+    HashTree, Principal, Serialize, Set, Signed, State, LABEL_ASSETS, LABEL_SIG,
 };
 
 #[derive(CandidType, Clone, Serialize)]
 pub struct Delegation {
     user: String,
     pubkey: ByteBuf,
+    signer: ByteBuf,
     session: ByteBuf,
     expiration: u64,
     targets: Set<Principal>,
@@ -24,6 +24,7 @@ impl Delegation {
         Ok(Delegation {
             user: user.to_string(),
             pubkey: Accounts::user_canister_public_key(user)?,
+            signer: Accounts::signer_public_key(user)?,
             session: ByteBuf::from(session),
             expiration,
             targets: targets.collect(),
@@ -35,7 +36,7 @@ impl Delegation {
     }
 
     pub fn seed(&self) -> Hash {
-        utils::seed(&self.user)
+        Accounts::seed(&self.user)
     }
 
     pub fn seed_hash(&self) -> Hash {
@@ -46,6 +47,11 @@ impl Delegation {
         &self.pubkey
     }
 
+    /// Public key the delegation is signed with, the principal key unless migrated.
+    pub fn signer(&self) -> &[u8] {
+        &self.signer
+    }
+
     pub fn principal(&self) -> Principal {
         Principal::self_authenticating(self.pubkey())
     }
@@ -58,20 +64,10 @@ impl Delegation {
         hash::bytes(self.session())
     }
 
-    fn set_session(mut self, session: &[u8]) -> Self {
-        self.session = ByteBuf::from(session);
-        self
-    }
-
     pub fn expiration(&self) -> u64 {
         self.expiration
     }
 
-    #[allow(unused)]
-    pub fn timestamp(&self) -> Timestamp {
-        Timestamp::from(self.expiration)
-    }
-
     pub fn targets(&self) -> impl Iterator<Item = &Principal> {
         self.targets.iter()
     }
@@ -97,13 +93,19 @@ impl Delegation {
         hash::domain(b"ic-request-auth-delegation", &hash)
     }
 
-    pub(crate) fn store(&self) -> Hash {
+    pub(crate) fn store(&self, label: Option<&str>) -> Hash {
         State::with(|state| {
             let signatures = &mut *state.signatures().borrow_mut();
 
-            let hash = signatures.put(self.seed_hash(), self.hash());
+            let signed = Signed::new(
+                self.session(),
+                self.targets().copied(),
+                self.expiration(),
+                label,
+            );
+            let hash = signatures.put(self.seed_hash(), self.hash(), signed);
 
-            state.update_root_hash(&signatures);
+            state.update_root_hash(signatures);
 
             Accounts::borrow_mut(|a| a.store(self.user(), &self.principal()));
 
@@ -118,9 +120,16 @@ impl Delegation {
         State::with(|state| {
             let signatures = &mut *state.signatures().borrow_mut();
 
+            if signatures
+                .get(self.seed_hash(), hash)
+                .is_some_and(|signed| signed.revoked())
+            {
+                return Err("Delegation has been revoked".to_owned());
+            }
+
             let witness = signatures
-                .witness(hash::bytes(self.seed()), hash)
-                .ok_or(format!("Signature not found"))?;
+                .witness(self.seed_hash(), hash)
+                .ok_or("Signature not found".to_owned())?;
 
             let reconstructed = witness.reconstruct();
             let root_hash = signatures.root_hash();
@@ -150,7 +159,7 @@ impl Delegation {
                     targets: self.targets.clone(),
                 },
                 signature,
-                pubkey: self.pubkey.clone(),
+                pubkey: self.signer.clone(),
             })
         })
     }
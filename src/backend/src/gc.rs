@@ -0,0 +1,73 @@
+use crate::types::*;
+
+/// Interval between garbage collection runs.
+const INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Entries removed by garbage collection.
+#[derive(CandidType, Clone, Copy, Default, Serialize)]
+pub struct Collected {
+    pub signatures: u64,
+    pub messages: u64,
+    pub proofs: u64,
+    pub nonces: u64,
+}
+
+impl Collected {
+    fn add(&mut self, other: &Self) {
+        self.signatures += other.signatures;
+        self.messages += other.messages;
+        self.proofs += other.proofs;
+        self.nonces += other.nonces;
+    }
+}
+
+/// Garbage collection statistics since the canister was installed or last upgraded.
+#[derive(CandidType, Clone, Default, Serialize)]
+pub struct Collection {
+    pub runs: u64,
+    pub last_run: Option<Timestamp>,
+    pub last: Collected,
+    pub total: Collected,
+}
+
+/// Starts the periodic garbage collection. Timers do not survive upgrades, so this runs in both
+/// `init` and `post_upgrade`.
+pub(crate) fn start() {
+    ic_cdk_timers::set_timer_interval(INTERVAL, || {
+        collect();
+    });
+}
+
+/// Removes every expired signature, message, consumed proof and link nonce, then certifies the
+/// signatures root hash once.
+pub(crate) fn collect() -> Collected {
+    let signatures = State::with(|state| {
+        let signatures = &mut *state.signatures().borrow_mut();
+        let pruned = signatures.prune_all();
+
+        if pruned > 0 {
+            state.update_root_hash(signatures);
+        }
+
+        pruned
+    });
+    let messages = states::messages::borrow_mut(Messages::expire);
+    let proofs = Proofs::borrow_mut(Proofs::prune);
+    let nonces = Accounts::borrow_mut(|accounts| accounts.prune_nonces(utils::now()));
+
+    let collected = Collected {
+        signatures: signatures as u64,
+        messages: messages as u64,
+        proofs: proofs as u64,
+        nonces: nonces as u64,
+    };
+
+    states::collection::borrow_mut(|collection| {
+        collection.runs += 1;
+        collection.last_run = Some(utils::now().into());
+        collection.last = collected;
+        collection.total.add(&collected);
+    });
+
+    collected
+}
@@ -20,27 +20,27 @@ impl Global {
     }
 }
 
-impl Into<Pair> for Global {
-    fn into(self) -> Pair {
-        self.pair()
+impl From<Global> for Pair {
+    fn from(global: Global) -> Self {
+        global.pair()
     }
 }
 
-impl Into<Pair> for &Global {
-    fn into(self) -> Pair {
-        self.pair()
+impl From<&Global> for Pair {
+    fn from(global: &Global) -> Self {
+        global.pair()
     }
 }
 
-impl Into<ByteBuf> for Global {
-    fn into(self) -> ByteBuf {
-        self.value
+impl From<Global> for ByteBuf {
+    fn from(global: Global) -> Self {
+        global.value
     }
 }
 
-impl Into<ByteBuf> for &Global {
-    fn into(self) -> ByteBuf {
-        self.value.clone()
+impl From<&Global> for ByteBuf {
+    fn from(global: &Global) -> Self {
+        global.value.clone()
     }
 }
 
@@ -81,7 +81,7 @@ impl Globals {
     pub fn all() -> impl Iterator<Item = Pair> {
         let globals = Self::borrow(|Self(m)| m.iter().collect::<Map<_, _>>());
 
-        globals.into_iter().map(|(_, g)| g.into())
+        globals.into_values().map(Into::into)
     }
 
     pub fn get(key: impl AsRef<str>) -> Option<ByteBuf> {
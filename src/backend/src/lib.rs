@@ -6,9 +6,10 @@
 //!
 //! ## Security Considerations
 //!
-//! **Important**: This implementation lacks a message signing step for Telegram ID ownership
-//! verification, which makes it less secure than SIWB (Sign In With Bitcoin). Users should
-//! be aware that Telegram ID ownership cannot be cryptographically verified.
+//! Telegram ID ownership is verified in `prepare` by checking the Telegram proof (Mini App
+//! `initData` or Login Widget data) against the bot it was issued for, as configured in the
+//! canister settings. The Telegram user id is taken from the verified payload, never from the
+//! caller. Bot tokens are secrets: they are never returned by the `setting` query.
 //!
 //! ## Features
 //!
@@ -26,20 +27,29 @@
 //! - Global state operations
 //! - Configuration management
 
-use ic_cdk::{init, query, update};
+use ic_cdk::{init, post_upgrade, query, update};
 use types::*;
 
 mod accounts;
+mod application;
+mod bot;
 mod canisters;
 mod delegation;
+mod gc;
 mod globals;
 mod messages;
 mod payloads;
+mod proofs;
 mod responses;
+mod scope;
 mod services;
+mod session;
 mod setting;
 mod signatures;
 mod state;
+mod target;
+mod telegram;
+mod template;
 mod timestamp;
 mod types;
 
@@ -48,12 +58,18 @@ pub mod states;
 pub mod utils;
 
 pub use crate::accounts::Accounts;
+pub use crate::bot::Bot;
 pub use crate::delegation::Delegation;
+pub use crate::gc::{Collected, Collection};
 pub use crate::globals::Globals;
 pub use crate::messages::{Message, Messages};
+pub use crate::proofs::Proofs;
 pub use crate::setting::Setting;
 pub use crate::signatures::Signatures;
 pub use crate::state::{State, LABEL_ASSETS, LABEL_SIG};
+pub use crate::target::Target;
+pub use crate::telegram::{InitData, Proof, Verification, Widget};
+pub use crate::template::Format;
 pub use crate::timestamp::Timestamp;
 
 /// Checks if the current caller is authorized to access protected endpoints.
@@ -78,6 +94,26 @@ pub fn authorized() -> Result<(), String> {
     Err("Unauthorized".to_owned())
 }
 
+/// Checks if the current caller may access the self-service authentication endpoints.
+///
+/// Authorized principals always can. Any other caller, including the anonymous principal,
+/// can only when at least one bot in the settings allows public login. Endpoints guarded by
+/// this function check the bot of each request again.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the caller is authorized or public login is enabled
+/// * `Err(String)` - Otherwise, with error message "Unauthorized"
+pub fn public() -> Result<(), String> {
+    let setting = states::setting::get();
+
+    if setting.authorized(&caller_principal()) || setting.public() {
+        return Ok(());
+    }
+
+    Err("Unauthorized".to_owned())
+}
+
 /// Initializes the canister with the provided settings.
 ///
 /// This function is called once when the canister is first deployed.
@@ -89,35 +125,55 @@ pub fn authorized() -> Result<(), String> {
 ///
 /// # Note
 ///
-/// This function can only be called during canister initialization.
+/// This function can only be called during canister initialization. Installation fails with
+/// the validation error if any bot, application, template or limit in `setting` is invalid.
 #[init]
 pub async fn init(setting: Setting) {
     setting.merge();
+    gc::start();
+}
+
+/// Restores heap state after an upgrade.
+///
+/// Settings, accounts, pending messages and delegation signatures are kept in stable memory.
+/// The certified signature tree is rebuilt from it and the certified data is set again, so
+/// delegations issued before the upgrade can still be fetched with `delegation`. Aliases and
+/// linked principals stored before their reverse indexes existed are indexed. Garbage
+/// collection is started again, as timers do not survive upgrades.
+#[post_upgrade]
+pub fn post_upgrade() {
+    State::with(State::restore);
+    Accounts::borrow_mut(Accounts::restore);
+    gc::start();
 }
 
-/// Returns a map of available features and their enabled status.
+/// Returns a report of the canister's capabilities.
 ///
-/// This query method allows clients to discover which optional features
-/// are compiled into this canister instance.
+/// This query method allows clients to discover which optional features are compiled into
+/// this canister instance and how it is configured, without probing methods one by one.
 ///
 /// # Returns
 ///
-/// A map where keys are feature names and values indicate if the feature is enabled:
-/// * `"ckbtc"` - Whether ckBTC integration is available
+/// * `api_version` - The canister API version
+/// * `cargo` - Cargo features and whether they are compiled in, e.g. `"ckbtc"`
+/// * `verifications` - Verification modes used by the configured bots
+/// * `bots` - Configured bots with their verification mode, public login and widget support
+/// * `standards` - Implemented ICRC standards, as in `icrc10_supported_standards`
+/// * `session_proof` - Whether `login` requires a session key signature
+/// * `target_allowlist` - Whether requested targets are checked against an allowlist
 ///
-/// # Example Response
+/// # Security Note
 ///
-/// ```json
-/// {
-///   "ckbtc": true
-/// }
-/// ```
+/// Bot tokens are never included; `widget` only tells whether a token is configured.
 #[query]
-pub async fn features() -> Map<&'static str, bool> {
-    let mut features = Map::new();
+pub async fn features() -> responses::Features {
+    services::features::features().await
+}
 
-    features.insert("ckbtc", cfg!(feature = "ckbtc"));
-    features
+/// Lists the ICRC standards implemented by this canister, as defined by ICRC-10.
+#[query]
+pub fn icrc10_supported_standards() -> Vec<responses::Standard> {
+    services::features::standards()
 }
 
 /// Retrieves the current canister settings.
@@ -134,7 +190,7 @@ pub async fn features() -> Map<&'static str, bool> {
 /// The current `Setting` configuration object containing all canister settings.
 #[query(name = "setting", guard = "authorized")]
 pub async fn setting() -> Setting {
-    states::setting::get()
+    states::setting::get().redacted()
 }
 
 /// Extends the current canister settings with additional configuration.
@@ -184,6 +240,370 @@ pub async fn set_expiration_minute(minute: u64) {
     setting.store();
 }
 
+/// Sets the bounds of the delegation expiration requested in `prepare`.
+///
+/// # Arguments
+///
+/// * `min` - The shortest expiration in minutes, one minute when unset
+/// * `max` - The longest expiration in minutes, the bot's or global expiration when unset
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can modify settings.
+///
+/// # Security Note
+///
+/// Allowlisted targets with their own expiration cap still apply on top of these bounds.
+#[update(name = "setExpirationBounds", guard = "authorized")]
+pub async fn set_expiration_bounds(
+    min: Option<u64>,
+    max: Option<u64>,
+) -> responses::SettingResponse {
+    let mut setting = states::setting::get();
+
+    setting
+        .set_expiration_bounds(min, max)
+        .map(|()| setting.store())
+        .into()
+}
+
+/// Registers or replaces a Telegram bot served by this canister.
+///
+/// # Arguments
+///
+/// * `id` - The Telegram bot id, the numeric prefix of its token
+/// * `bot` - The bot policy: token, verification mode, default canisters and expiration
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can modify settings.
+///
+/// # Behavior
+///
+/// With `Token` verification, the `initData` `hash` field is checked with the bot token. With
+/// `Signature`, the Ed25519 `signature` field is checked against Telegram's public keys, so the
+/// canister does not need to hold the bot token. Login Widget data always needs the token.
+///
+/// # Security Note
+///
+/// Bot tokens are kept in stable memory and are never returned by the `setting` query. A token
+/// holder can produce proofs for any Telegram user. Bots give users their canister-wide
+/// principal unless they are `isolated`, in which case users get a principal seeded on the
+/// bot id. Isolation is opt-in, so bots configured before an upgrade and bots added later keep
+/// their users' principals; isolating a bot that already has users changes their principals.
+#[update(name = "setBot", guard = "authorized")]
+pub async fn set_bot(id: u64, bot: Bot) -> responses::SettingResponse {
+    let mut setting = states::setting::get();
+
+    setting.set_bot(id, bot).map(|()| setting.store()).into()
+}
+
+/// Removes a Telegram bot, so `prepare` rejects its proofs.
+///
+/// # Arguments
+///
+/// * `id` - The Telegram bot id
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can modify settings.
+///
+/// # Returns
+///
+/// `true` if the bot was configured.
+#[update(name = "removeBot", guard = "authorized")]
+pub async fn remove_bot(id: u64) -> bool {
+    let mut setting = states::setting::get();
+    let removed = setting.remove_bot(id).is_some();
+    setting.store();
+
+    removed
+}
+
+/// Adds or replaces a canister in the allowlist of delegation targets.
+///
+/// # Arguments
+///
+/// * `canister` - The target canister
+/// * `target` - The target policy: expiration cap and whether it is granted by default
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can modify settings.
+///
+/// # Security Note
+///
+/// Once a target is set, `prepare` rejects requested canisters outside the allowlist, so a
+/// relay cannot obtain delegations for arbitrary canisters. Canisters from the settings and
+/// bot policies are trusted and always granted.
+#[update(name = "setTarget", guard = "authorized")]
+pub async fn set_target(canister: Principal, target: Target) {
+    let mut setting = states::setting::get();
+    setting.set_target(canister, target);
+    setting.store();
+}
+
+/// Removes a canister from the allowlist of delegation targets.
+///
+/// # Arguments
+///
+/// * `canister` - The target canister
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can modify settings.
+///
+/// # Returns
+///
+/// `true` if the canister was allowed. The allowlist stays enforced when it becomes empty.
+#[update(name = "removeTarget", guard = "authorized")]
+pub async fn remove_target(canister: Principal) -> bool {
+    let mut setting = states::setting::get();
+    let removed = setting.remove_target(&canister).is_some();
+    setting.store();
+
+    removed
+}
+
+/// Registers or replaces an application whose users get principals of their own.
+///
+/// # Arguments
+///
+/// * `id` - The application id, mixed into the seed of its users' principals
+/// * `application` - The derivation origins of the application, its name, the bots its origins
+///   sign in with and the canisters only it may request
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can modify settings.
+///
+/// # Behavior
+///
+/// `prepare` with an `origin` of the application issues delegations for principals seeded on
+/// the user and the application id, so unrelated applications cannot correlate their users.
+/// Every origin of an application derives the same principals: to move to a new domain or
+/// frontend canister, add the new origin next to the old one.
+///
+/// # Security Note
+///
+/// Origins are supplied by callers, so `prepare` with an origin only accepts proofs of the
+/// application's bots and requested canisters of the application. Its canisters cannot be
+/// requested with another origin or without one.
+///
+/// Principals depend on the id only. Removing an application and registering it again under
+/// the same id restores its users' principals; a different id gives them new ones.
+#[update(name = "setApplication", guard = "authorized")]
+pub async fn set_application(id: String, application: Application) -> responses::SettingResponse {
+    let mut setting = states::setting::get();
+
+    setting
+        .set_application(&id, application)
+        .map(|()| setting.store())
+        .into()
+}
+
+/// Removes an application, so `prepare` rejects its origins.
+///
+/// # Arguments
+///
+/// * `id` - The application id
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can modify settings.
+///
+/// # Returns
+///
+/// `true` if the application was registered.
+#[update(name = "removeApplication", guard = "authorized")]
+pub async fn remove_application(id: String) -> bool {
+    let mut setting = states::setting::get();
+    let removed = setting.remove_application(&id).is_some();
+    setting.store();
+
+    removed
+}
+
+/// Sets the canister whose id is embedded in the public keys of user principals.
+///
+/// # Arguments
+///
+/// * `canister` - The SIWT canister this one replaces, or `None` to own principals itself
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can modify settings.
+///
+/// # Behavior
+///
+/// Once set, `login` asks that canister for a bridge delegation from the user's principal key
+/// to this canister's key for the user, and returns where to fetch it. Clients put the bridge
+/// before this canister's delegation, so principals stay the same across the migration. The
+/// other canister must name this one with `setSuccessor`.
+///
+/// # Security Note
+///
+/// Import the accounts of the other canister with `importAccounts` after setting this, so
+/// principals and seeds, including the deployment salt, match.
+#[update(name = "setSigningCanister", guard = "authorized")]
+pub async fn set_signing_canister(canister: Option<Principal>) {
+    let mut setting = states::setting::get();
+    setting.set_signing_canister(canister);
+    setting.store();
+}
+
+/// Sets the canister that replaced this one and may request bridge delegations.
+///
+/// # Arguments
+///
+/// * `canister` - The successor SIWT canister, or `None` to stop bridging
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can modify settings.
+///
+/// # Security Note
+///
+/// Bridge delegations only ever delegate to the successor's key for the same seed, and only
+/// the successor can sign with that key.
+#[update(name = "setSuccessor", guard = "authorized")]
+pub async fn set_successor(canister: Option<Principal>) {
+    let mut setting = states::setting::get();
+    setting.set_successor(canister);
+    setting.store();
+}
+
+/// Sets or removes the consent message template for a language.
+///
+/// # Arguments
+///
+/// * `language` - A language code such as Telegram's `language_code`, e.g. `en` or `pt-br`
+/// * `template` - The template, or `None` to remove it
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can modify settings.
+///
+/// # Behavior
+///
+/// Templates are plain text with the placeholders `{user}`, `{bot}`, `{canisters}`,
+/// `{expiration}` and `{duration}`. Canisters are shown with the names of their allowlist
+/// targets. `prepare` returns the message both as plain text and as Telegram `MarkdownV2`.
+#[update(name = "setTemplate", guard = "authorized")]
+pub async fn set_template(
+    language: String,
+    template: Option<String>,
+) -> responses::SettingResponse {
+    let mut setting = states::setting::get();
+
+    setting
+        .set_template(&language, template)
+        .map(|()| setting.store())
+        .into()
+}
+
+/// Sets the Telegram Ed25519 public keys accepted for `Signature` verification.
+///
+/// # Arguments
+///
+/// * `keys` - 32-byte public keys, or `None` to use Telegram's production key
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can modify settings.
+#[update(name = "setPublicKeys", guard = "authorized")]
+pub async fn set_public_keys(keys: Option<Set<ByteBuf>>) {
+    let mut setting = states::setting::get();
+    setting.set_public_keys(keys);
+    setting.store();
+}
+
+/// Sets the maximum age in minutes of a Telegram proof's `auth_date`.
+///
+/// # Arguments
+///
+/// * `minute` - The maximum age in minutes of a proof accepted by `prepare`
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can modify settings.
+///
+/// # Security Note
+///
+/// Every proof can be used only once. Consumed proofs are remembered in stable memory for
+/// this long, after which they are rejected as expired instead. Raising the window does not
+/// re-admit proofs that are already outside the current one.
+#[update(name = "setProofExpirationMinute", guard = "authorized")]
+pub async fn set_proof_expiration_minute(minute: u64) {
+    let mut setting = states::setting::get();
+    setting.set_proof_expiration_minute(minute);
+    setting.store();
+}
+
+/// Sets how long a prepared message waits for `login`.
+///
+/// # Arguments
+///
+/// * `minute` - The lifetime in minutes of a message returned by `prepare`
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can modify settings.
+#[update(name = "setMessageExpirationMinute", guard = "authorized")]
+pub async fn set_message_expiration_minute(minute: u64) {
+    let mut setting = states::setting::get();
+    setting.set_message_expiration_minute(minute);
+    setting.store();
+}
+
+/// Sets the maximum number of pending prepared messages.
+///
+/// # Arguments
+///
+/// * `max` - The maximum overall, or `None` for the default
+/// * `max_user` - The maximum per user, or `None` for the default
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can modify settings.
+///
+/// # Behavior
+///
+/// When a limit is reached, `prepare` evicts the oldest pending messages to make room.
+#[update(name = "setMessageLimits", guard = "authorized")]
+pub async fn set_message_limits(
+    max: Option<u64>,
+    max_user: Option<u64>,
+) -> responses::SettingResponse {
+    let mut setting = states::setting::get();
+
+    setting
+        .set_message_limits(max, max_user)
+        .map(|()| setting.store())
+        .into()
+}
+
+/// Sets whether `login` requires proof of possession of the session key.
+///
+/// # Arguments
+///
+/// * `required` - Whether a signature over the prepared hash is required in `login`
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can modify settings.
+///
+/// # Security Note
+///
+/// Without it, a relay could bind a session key it does not control to a user. A signature
+/// supplied in `login` is always verified, even when it is not required.
+#[update(name = "setSessionProof", guard = "authorized")]
+pub async fn set_session_proof(required: bool) {
+    let mut setting = states::setting::get();
+    setting.set_session_proof(required);
+    setting.store();
+}
+
 #[update(name = "accountDerivedAddress", guard = "authorized")]
 async fn account_derived_address(
     payload: payloads::AccountDerivedAddressPayload,
@@ -191,14 +611,258 @@ async fn account_derived_address(
     services::accounts::derived(payload).await.into()
 }
 
+/// Looks up the principal of a user by Telegram id, username (with or without `@`) or legacy
+/// user string.
 #[query(guard = "authorized")]
 async fn principal(user: String) -> Option<Principal> {
-    states::accounts::borrow(|accounts| accounts.principal(&user))
+    states::accounts::borrow(|accounts| accounts.find(&user))
+}
+
+/// Returns the Telegram identity behind a principal: its user string, Telegram id and current
+/// username.
+#[query(guard = "authorized")]
+async fn identity(principal: Principal) -> Option<responses::Identity> {
+    states::accounts::borrow(|accounts| {
+        let user = accounts.user(&principal)?;
+        let id = accounts.id(&user);
+        let scope = accounts.scope(&user);
+
+        Some(responses::Identity {
+            username: id.and_then(|id| accounts.username(id)),
+            bot: scope.as_ref().and_then(Scope::bot),
+            profile: scope
+                .as_ref()
+                .and_then(|scope| scope.profile().map(str::to_owned)),
+            application: scope.and_then(|scope| scope.application().map(str::to_owned)),
+            salted: accounts.salted(&user),
+            user,
+            id,
+        })
+    })
+}
+
+/// Generates the deployment salt mixed into the seed of new principals.
+///
+/// # Returns
+///
+/// The number of existing accounts flagged as unsalted.
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can enable it.
+///
+/// # Security Note
+///
+/// Without a salt, anyone who knows a Telegram id can derive that user's principal. The salt is
+/// 32 bytes from `raw_rand`, kept in stable memory, never returned and never changed once set.
+/// Accounts that exist when it is generated keep their unsalted principal.
+#[update(name = "enableSalt", guard = "authorized")]
+async fn enable_salt() -> responses::EnableSaltResponse {
+    services::accounts::enable_salt().await.into()
+}
+
+/// Aliases a legacy account to a Telegram id.
+///
+/// # Arguments
+///
+/// * `legacy` - The user string of an account seeded on something other than a Telegram id
+/// * `id` - The Telegram user id
+///
+/// # Returns
+///
+/// The legacy principal, which the Telegram user gets from now on when logging in.
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can alias accounts.
+///
+/// # Security Note
+///
+/// Fails when the Telegram user already has an account of its own, which would be orphaned, or
+/// when the legacy account is aliased to another Telegram user.
+#[update(guard = "authorized")]
+async fn alias(legacy: String, id: u64) -> responses::AliasResponse {
+    states::accounts::borrow_mut(|accounts| accounts.alias(&legacy, id)).into()
 }
 
+/// Looks up the user of an account principal or of a principal linked to it.
 #[query(guard = "authorized")]
 async fn user(principal: Principal) -> Option<String> {
-    states::accounts::borrow(|accounts| accounts.user(&principal))
+    states::accounts::borrow(|accounts| accounts.owner(&principal))
+}
+
+/// Lists the principals linked to a user, given by Telegram id, username or legacy user string.
+#[query(guard = "authorized")]
+async fn links(user: String) -> Vec<Principal> {
+    states::accounts::borrow(|accounts| {
+        accounts
+            .resolve(&user)
+            .map(|user| accounts.linked(&user))
+            .unwrap_or_default()
+    })
+}
+
+/// Lists the profile and application principals of a user, given by Telegram id, username or
+/// legacy user string, keyed by their user string. Only those the user has logged in with are
+/// listed; `identity` tells the profile and application of each.
+#[query(guard = "authorized")]
+async fn profiles(user: String) -> Map<String, Principal> {
+    states::accounts::borrow(|accounts| {
+        accounts
+            .resolve(&user)
+            .map(|user| accounts.profiles(&user))
+            .unwrap_or_default()
+    })
+}
+
+/// Erases a user's data for a privacy request.
+///
+/// # Arguments
+///
+/// * `user` - The Telegram id, username or legacy user string
+///
+/// # Returns
+///
+/// What was removed: account, profile and application principals, linked principals, deleted
+/// delegations, pending messages, and whether a username and an alias were removed.
+///
+/// # Authorization
+///
+/// This endpoint requires authorization.
+///
+/// # Behavior
+///
+/// Delegations are deleted with their session keys and labels, and removed from the certified
+/// tree, so `delegation` no longer returns them.
+///
+/// Logging in again creates a new account. A Telegram user seeded on its id gets the same
+/// principal back only if it was created after the deployment salt, or no salt is set. Users
+/// of an aliased legacy account and users created before the salt lose that principal for
+/// good, since the alias and the unsalted flag are erased.
+#[update(guard = "authorized")]
+async fn erase(user: String) -> responses::EraseResponse {
+    services::accounts::erase(user).await.into()
+}
+
+/// Erases the caller's data, as `erase` does, when self-service erasure is enabled.
+///
+/// # Authorization
+///
+/// The caller must be signed in with a delegation issued by this canister. Every profile and
+/// application principal of the caller's Telegram user is erased too.
+#[update]
+async fn forget() -> responses::EraseResponse {
+    services::accounts::forget().await.into()
+}
+
+/// Enables or disables self-service erasure with `forget`.
+///
+/// # Arguments
+///
+/// * `enabled` - Whether users may erase their own data
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can modify settings.
+#[update(name = "setSelfErasure", guard = "authorized")]
+async fn set_self_erasure(enabled: bool) {
+    let mut setting = states::setting::get();
+    setting.set_self_erasure(enabled);
+    setting.store();
+}
+
+/// Exports a page of accounts for migration to another SIWT canister.
+///
+/// # Arguments
+///
+/// * `start` - The `next` of the previous page, or `None` for the first page
+/// * `limit` - The page size, at most 1000
+///
+/// # Authorization
+///
+/// This endpoint requires authorization.
+///
+/// # Security Note
+///
+/// The first page includes the deployment salt, which lets anyone derive principals from
+/// Telegram ids. Only hand it to the replacing canister.
+#[query(name = "exportAccounts", guard = "authorized")]
+async fn export_accounts(start: Option<Hash>, limit: u64) -> responses::AccountsPage {
+    services::accounts::export(start, limit).await
+}
+
+/// Imports accounts exported from another SIWT canister with `exportAccounts`.
+///
+/// # Arguments
+///
+/// * `payload` - The accounts of a page, and the salt of the first page
+///
+/// # Returns
+///
+/// The number of accounts imported.
+///
+/// # Authorization
+///
+/// This endpoint requires authorization.
+///
+/// # Behavior
+///
+/// Every principal is checked against the signing canister set with `setSigningCanister`, so a
+/// page is imported entirely or not at all.
+#[update(name = "importAccounts", guard = "authorized")]
+async fn import_accounts(
+    payload: payloads::ImportAccountsPayload,
+) -> responses::ImportAccountsResponse {
+    services::accounts::import(payload).await.into()
+}
+
+/// Issues a nonce for linking a second principal, such as an Internet Identity, to the
+/// caller's account.
+///
+/// # Returns
+///
+/// A random nonce, valid for ten minutes and usable once, to pass to `link` from the second
+/// principal.
+///
+/// # Authorization
+///
+/// The caller must be signed in with a delegation issued by this canister.
+#[update(name = "linkNonce")]
+async fn link_nonce() -> responses::LinkNonceResponse {
+    services::accounts::link_nonce().await.into()
+}
+
+/// Links the caller to the user a nonce from `linkNonce` was issued for.
+///
+/// # Arguments
+///
+/// * `nonce` - The nonce from `linkNonce`
+///
+/// # Returns
+///
+/// The user the caller is now linked to.
+///
+/// # Security Note
+///
+/// Calling from the second principal proves control of it. A principal can be linked to one
+/// user only and cannot be the principal of an account itself.
+#[update]
+async fn link(nonce: ByteBuf) -> responses::LinkResponse {
+    services::accounts::link(nonce).await.into()
+}
+
+/// Unlinks a principal from its user.
+///
+/// # Arguments
+///
+/// * `principal` - The linked principal
+///
+/// # Authorization
+///
+/// The linked principal itself, the account principal of its user, or an authority.
+#[update]
+async fn unlink(principal: Principal) -> responses::UnlinkResponse {
+    services::accounts::unlink(principal).await.into()
 }
 
 #[query(guard = "authorized")]
@@ -209,22 +873,41 @@ async fn all() -> Map<String, Principal> {
 #[query]
 async fn caller() -> (Principal, Option<String>) {
     let principal = ic_cdk::caller();
-    let user = states::accounts::borrow(|accounts| accounts.user(&principal));
+    let user = states::accounts::borrow(|accounts| accounts.owner(&principal));
 
     (principal, user)
 }
 
-#[update(guard = "authorized")]
+#[update(guard = "public")]
 async fn prepare(payload: payloads::PreparePayload) -> responses::PrepareResponse {
     services::auth::prepare(payload).await.into()
 }
 
-#[update(guard = "authorized")]
+#[update(guard = "public")]
 async fn login(payload: payloads::LoginPayload) -> responses::LoginResponse {
     services::auth::login(payload).await.into()
 }
 
-#[query(guard = "authorized")]
+/// Delegates from a user's principal key to the successor canister's key for the user.
+///
+/// # Arguments
+///
+/// * `payload` - The user string, the successor's key for it, the expiration and the targets
+/// * `scope` - The profile and application scope the user string stands for, if any. It is
+///   registered here when the user first logs in with it after the migration.
+///
+/// # Authorization
+///
+/// Only the successor set with `setSuccessor` may call it, from its `login`.
+#[update]
+async fn bridge(
+    payload: payloads::DelegationPayload,
+    scope: Option<Scope>,
+) -> responses::BridgeResponse {
+    services::auth::bridge(payload, scope).await.into()
+}
+
+#[query(guard = "public")]
 async fn delegation(payload: payloads::DelegationPayload) -> responses::SignedDelegationResponse {
     let certificate = data_certificate().expect("delegation must be called using a query call");
 
@@ -233,6 +916,102 @@ async fn delegation(payload: payloads::DelegationPayload) -> responses::SignedDe
         .into()
 }
 
+/// Counts prepared messages waiting for `login`.
+///
+/// # Arguments
+///
+/// * `user` - A Telegram user ID, username or legacy user string to count only the messages of
+///   that user, across its bot, profile and application principals
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can count messages.
+#[query(name = "pendingMessages", guard = "authorized")]
+async fn pending_messages(user: Option<String>) -> u64 {
+    services::auth::pending_messages(user).await
+}
+
+/// Returns garbage collection statistics.
+///
+/// Expired delegation signatures, prepared messages, consumed proofs and link nonces are
+/// removed every five minutes by a timer, and the certified data is updated once per run.
+///
+/// # Returns
+///
+/// The number of runs, the time of the last run and what it removed, and the totals since the
+/// canister was installed or last upgraded.
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can read it.
+#[query(guard = "authorized")]
+async fn collection() -> Collection {
+    states::collection::get()
+}
+
+/// Lists the active delegations of a Telegram user.
+///
+/// # Arguments
+///
+/// * `user` - The Telegram user ID
+///
+/// # Returns
+///
+/// Unexpired, unrevoked delegations of every principal of the user, including its bot, profile
+/// and application principals, oldest first, with their session public key, targets,
+/// expiration, creation time and the device label given in `prepare`.
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can list sessions.
+#[query(guard = "authorized")]
+async fn sessions(user: String) -> Vec<responses::Session> {
+    services::auth::sessions(user).await
+}
+
+/// Revokes issued delegations before they expire.
+///
+/// # Arguments
+///
+/// * `payload` - A delegation hash as returned by `login`, a session public key, or a user
+///
+/// # Returns
+///
+/// The number of delegations revoked.
+///
+/// # Authorization
+///
+/// This endpoint requires authorization. Only principals in the authorized list can revoke.
+///
+/// # Security Note
+///
+/// Revoked delegations are removed from the certified signature tree, so `delegation` can no
+/// longer return them and reports them as revoked. Signed delegations that were already
+/// fetched stay valid until their expiration.
+#[update(guard = "authorized")]
+async fn revoke(payload: payloads::RevokePayload) -> u64 {
+    services::auth::revoke(payload).await
+}
+
+/// Returns an ICRC-21 consent message for a call to one of this canister's update methods.
+///
+/// # Arguments
+///
+/// * `request` - The method, its candid-encoded arguments and the user's display preferences
+///
+/// # Behavior
+///
+/// Messages for `prepare` and `login` are rendered from the prepared message with the template
+/// for the requested language, as in `prepare`. `prepare` proofs are verified but not consumed.
+/// Administrative methods such as `globalsStore` and the settings mutators are described in
+/// English. Times are shown in UTC.
+#[update]
+async fn icrc21_canister_call_consent_message(
+    request: payloads::ConsentMessageRequest,
+) -> responses::ConsentMessageResponse {
+    services::icrc21::consent(request).await.into()
+}
+
 #[query(name = "globals", guard = "authorized")]
 async fn globals() -> Map<String, ByteBuf> {
     services::globals::all().await.collect()
@@ -1,52 +1,205 @@
+use crate::template;
 use crate::types::*;
 
+/// Version of the `Message::hash` encoding. Version 2 adds the delegation lifetime, the label
+/// and the creation time to the fields of version 1. Released canisters kept messages on the heap
+/// only, so an upgrade never leaves an unversioned message behind.
+const MESSAGE_VERSION: u64 = 2;
+
+/// Domain separator of versioned message hashes.
+const MESSAGE_DOMAIN: &[u8] = b"siwt-prepare-message";
+
 #[derive(CandidType, Clone, Deserialize, Serialize)]
 pub struct Message {
     user: String,
+    bot: u64,
     session: ByteBuf,
     expiration: u64,
     canisters: Set<Principal>,
+    #[serde(default)]
+    label: Option<String>,
+    #[serde(default)]
+    expiration_minute: Option<u64>,
+    #[serde(default)]
+    created: u64,
+    #[serde(default)]
+    version: u64,
+    #[serde(default)]
+    language: Option<String>,
 }
 
-#[derive(CandidType, Clone, Deserialize, Serialize)]
+/// Prepared messages waiting for `login`, kept in stable memory so upgrades do not drop them.
+///
+/// Besides the expiration index used by `prune`, messages are indexed by creation time, overall
+/// and per user, so the oldest ones can be evicted when the pending limits are reached.
 pub struct Messages {
-    map: Map<Hash, Message>,
+    map: StableBTreeMap<Hash, Message, VM>,
+    expirations: StableBTreeMap<(u64, Hash), (), VM>,
+    created: StableBTreeMap<(u64, Hash), (), VM>,
+    users: StableBTreeMap<(Hash, u64, Hash), (), VM>,
 }
 
 impl Messages {
-    pub fn new() -> Messages {
-        Messages { map: Map::new() }
+    fn new(memories: [VM; 4]) -> Self {
+        let [m1, m2, m3, m4] = memories;
+
+        Self {
+            map: StableBTreeMap::init(m1),
+            expirations: StableBTreeMap::init(m2),
+            created: StableBTreeMap::init(m3),
+            users: StableBTreeMap::init(m4),
+        }
+    }
+
+    pub(crate) fn init(memories: [VM; 4]) -> RefCell<Self> {
+        RefCell::new(Self::new(memories))
     }
 
     pub fn prune(&mut self) -> &mut Self {
+        self.expire();
+        self
+    }
+
+    /// Removes every expired message and returns how many were removed.
+    pub fn expire(&mut self) -> usize {
         let now = utils::now();
+        let expired = self
+            .expirations
+            .iter()
+            .take_while(|((expiration, _), _)| *expiration <= now)
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
 
-        self.map.retain(|_, message| message.expiration > now);
-        self
+        for (_, hash) in &expired {
+            self.remove(hash);
+        }
+
+        expired.len()
     }
 
     pub fn insert(&mut self, message: Message) -> Option<Message> {
-        self.map.insert(message.hash(), message)
+        let hash = message.hash();
+        let previous = self.remove(&hash);
+
+        self.expirations.insert((message.expiration, hash), ());
+        self.created.insert((message.created, hash), ());
+        self.users
+            .insert((message.user_hash(), message.created, hash), ());
+        self.map.insert(hash, message);
+
+        previous
     }
 
-    #[allow(unused)]
-    pub fn get(&self, hash: &Hash) -> Option<Message> {
-        self.map.get(hash).cloned()
+    /// Removes the oldest messages until one more fits within `max` pending messages overall
+    /// and `max_user` for the message's user.
+    pub fn evict(&mut self, message: &Message, max: u64, max_user: u64) -> usize {
+        let user = message.user_hash();
+        let hash = message.hash();
+        let mut evicted = 0;
+
+        let oldest = self
+            .users
+            .range((user, 0, [0; 32])..=(user, u64::MAX, [u8::MAX; 32]))
+            .map(|((_, _, h), _)| h)
+            .filter(|h| *h != hash)
+            .collect::<Vec<_>>();
+        let excess = (oldest.len() as u64 + 1).saturating_sub(max_user) as usize;
+
+        for h in oldest.into_iter().take(excess) {
+            self.remove(&h);
+            evicted += 1;
+        }
+
+        while self.map.len() + 1 > max {
+            let Some(h) = self
+                .created
+                .iter()
+                .map(|((_, h), _)| h)
+                .find(|h| *h != hash)
+                .or_else(|| self.map.iter().map(|(h, _)| h).find(|h| *h != hash))
+            else {
+                break;
+            };
+
+            self.remove(&h);
+            evicted += 1;
+        }
+
+        evicted
+    }
+
+    /// Number of unexpired messages.
+    pub fn pending(&self) -> u64 {
+        let now = utils::now();
+        let expired = self
+            .expirations
+            .iter()
+            .take_while(|((expiration, _), _)| *expiration <= now)
+            .count() as u64;
+
+        self.map.len().saturating_sub(expired)
+    }
+
+    /// Number of unexpired messages for one user string.
+    pub fn pending_user(&self, user: &str) -> u64 {
+        let user = hash::bytes(user);
+
+        self.users
+            .range((user, 0, [0; 32])..=(user, u64::MAX, [u8::MAX; 32]))
+            .filter(|((_, _, hash), _)| self.find(hash).is_some())
+            .count() as u64
+    }
+
+    pub fn find(&self, hash: &Hash) -> Option<Message> {
+        self.map
+            .get(hash)
+            .filter(|message| message.expiration > utils::now())
     }
 
     pub fn remove(&mut self, hash: &Hash) -> Option<Message> {
-        self.map.remove(hash)
+        let message = self.map.remove(hash)?;
+
+        self.expirations.remove(&(message.expiration, *hash));
+        self.created.remove(&(message.created, *hash));
+        self.users
+            .remove(&(message.user_hash(), message.created, *hash));
+
+        Some(message)
     }
 
+    /// Removes every message of a user, pending or expired, and returns how many were removed.
+    pub fn remove_user(&mut self, user: &str) -> usize {
+        let user = hash::bytes(user);
+        let hashes = self
+            .users
+            .range((user, 0, [0; 32])..=(user, u64::MAX, [u8::MAX; 32]))
+            .map(|((_, _, hash), _)| hash)
+            .collect::<Vec<_>>();
+
+        hashes
+            .iter()
+            .filter(|hash| self.remove(hash).is_some())
+            .count()
+    }
+
+    /// Stores a prepared message, first evicting the oldest ones beyond the pending limits.
     pub fn put(message: &Message) -> Option<Message> {
-        Self::borrow_mut(|m| m.insert(message.clone()))
+        let setting = Setting::get();
+
+        Self::borrow_mut(|m| {
+            m.evict(message, setting.max_messages(), setting.max_user_messages());
+            m.insert(message.clone())
+        })
+    }
+
+    pub fn get(hash: &Hash) -> Option<Message> {
+        Self::borrow(|m| m.find(hash))
     }
 
     pub fn delete(hash: &Hash) -> Option<Message> {
         Self::borrow_mut(|m| m.remove(hash))
     }
 
-    #[allow(unused)]
     pub fn borrow<F: FnOnce(&Self) -> R, R>(f: F) -> R {
         states::messages::borrow(f)
     }
@@ -56,36 +209,58 @@ impl Messages {
     }
 }
 
-impl Default for Messages {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
 impl Message {
     pub fn new(
         user: &str,
+        bot: u64,
         session: impl AsRef<[u8]>,
         canisters: impl Iterator<Item = Principal>,
+        expiration_minute: Option<u64>,
     ) -> Self {
         let setting = Setting::get();
+        let canisters = setting.targets_of(bot, canisters);
+        let expiration_minute = setting.expiration_minute_of(bot, &canisters, expiration_minute);
 
         Self {
             user: user.to_owned(),
+            bot,
             session: ByteBuf::from(session.as_ref()),
-            expiration: utils::now_add_minute(10),
-            canisters: setting.canisters().copied().chain(canisters).collect(),
+            expiration: utils::now_add_minute(setting.message_expiration_minute()),
+            canisters,
+            label: None,
+            expiration_minute: Some(expiration_minute),
+            created: utils::now(),
+            version: MESSAGE_VERSION,
+            language: None,
         }
     }
 
+    pub(crate) fn set_label(mut self, label: Option<&str>) -> Self {
+        self.label = label.map(str::to_owned);
+        self
+    }
+
+    pub(crate) fn set_language(mut self, language: Option<&str>) -> Self {
+        self.language = language.map(str::to_owned);
+        self
+    }
+
     pub fn user(&self) -> &str {
         &self.user
     }
 
+    pub fn bot(&self) -> u64 {
+        self.bot
+    }
+
     pub fn session(&self) -> &[u8] {
         &self.session
     }
 
+    fn user_hash(&self) -> Hash {
+        hash::bytes(&self.user)
+    }
+
     pub fn expiration(&self) -> u64 {
         self.expiration
     }
@@ -94,20 +269,78 @@ impl Message {
         self.canisters.iter()
     }
 
-    pub fn to_vec(&self) -> Vec<u8> {
-        let user = self.user.as_bytes().to_vec();
-        let session = self.session.to_vec();
+    /// Lifetime in minutes of the delegation issued in `login`, resolved in `prepare`. Messages
+    /// prepared before it was recorded have none.
+    pub fn expiration_minute(&self) -> Option<u64> {
+        self.expiration_minute
+    }
+
+    /// Language code given in `prepare`, selecting the consent message template.
+    pub fn language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    /// Human-readable consent message from the template for the message language.
+    pub fn render(&self, setting: &Setting, format: Format) -> String {
         let canisters = self
             .canisters()
-            .flat_map(|p| p.as_slice())
-            .cloned()
-            .collect::<Vec<_>>();
+            .map(|canister| setting.canister_name(canister))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let values = [
+            ("user", self.user.clone()),
+            ("bot", self.bot.to_string()),
+            ("canisters", canisters),
+            ("expiration", Timestamp::from(self.expiration).to_string()),
+            (
+                "duration",
+                self.expiration_minute.unwrap_or_default().to_string(),
+            ),
+        ];
+
+        let (_, template) = setting.template(self.language());
+
+        template::render(template, &values, format)
+    }
+
+    /// Device label given in `prepare`, kept with the delegation issued in `login`.
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    pub(crate) fn map(&self) -> HashMap<&str, hash::Value<'_>> {
+        let mut map = HashMap::new();
+        let canisters = self
+            .canisters()
+            .map(|principal| hash::Value::Bytes(principal.as_slice()))
+            .collect();
+
+        map.insert("version", hash::Value::U64(self.version));
+        map.insert("user", hash::Value::String(&self.user));
+        map.insert("bot", hash::Value::U64(self.bot));
+        map.insert("session", hash::Value::Bytes(&self.session));
+        map.insert("expiration", hash::Value::U64(self.expiration));
+        map.insert("canisters", hash::Value::Array(canisters));
+
+        if self.version >= 2 {
+            map.insert("created", hash::Value::U64(self.created));
+
+            if let Some(minute) = self.expiration_minute {
+                map.insert("expiration_minute", hash::Value::U64(minute));
+            }
+
+            if let Some(label) = &self.label {
+                map.insert("label", hash::Value::String(label));
+            }
+        }
 
-        [user, session, canisters].concat().to_vec()
+        map
     }
 
+    /// Hash identifying the message in `login`, which the session key signs as proof of
+    /// possession.
     pub fn hash(&self) -> Hash {
-        hash::bytes(&self.to_vec())
+        hash::domain(MESSAGE_DOMAIN, &hash::map(self.map()))
     }
 }
 
@@ -125,14 +358,7 @@ impl Storable for Message {
 
 impl From<&Message> for String {
     fn from(message: &Message) -> Self {
-        let user = message.user();
-        let canisters = message
-            .canisters()
-            .map(Principal::to_text)
-            .collect::<Vec<_>>()
-            .join(", ");
-
-        format!("User {user} want to create delegation for accessing canister(s): {canisters}")
+        message.render(&Setting::get(), Format::Plain)
     }
 }
 
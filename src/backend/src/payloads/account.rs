@@ -1,4 +1,5 @@
-use crate::types::{CandidType, Deserialize};
+use crate::responses::AccountRecord;
+use crate::types::{ByteBuf, CandidType, Deserialize};
 
 #[cfg(feature = "ckbtc")]
 use crate::types::{Principal, Set};
@@ -6,19 +7,22 @@ use crate::types::{Principal, Set};
 #[derive(CandidType, Deserialize)]
 pub struct AccountDerivedAddressPayload {
     pub user: String,
+    /// Bot the user logs in with; only isolated bots change the principal.
+    pub bot: Option<u64>,
+    pub profile: Option<String>,
+    pub application: Option<String>,
     #[cfg(feature = "ckbtc")]
     pub ckbtc: AccountCkBtcPayload,
 }
 
-#[cfg(feature = "ckbtc")]
 #[derive(CandidType, Deserialize)]
-pub struct AccountCkBtcPayload {
-    pub owners: Set<Principal>,
+pub struct ImportAccountsPayload {
+    pub accounts: Vec<AccountRecord>,
+    pub salt: Option<ByteBuf>,
 }
 
+#[cfg(feature = "ckbtc")]
 #[derive(CandidType, Deserialize)]
-pub struct AccountDerivedBtcAddressPayload {
-    pub user: String,
-    #[cfg(feature = "ckbtc")]
+pub struct AccountCkBtcPayload {
     pub owners: Set<Principal>,
 }
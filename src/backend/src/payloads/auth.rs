@@ -1,18 +1,36 @@
+use crate::accounts::validate_profile;
+use crate::application::validate_origin;
 use crate::types::{
     canister_principal, from_der, ByteBuf, CandidType, Copied, Delegation, Deserialize, Hash,
-    Message, Principal, Set,
+    Message, Principal, Proof, Scope, Set,
 };
 
+/// Maximum length of a device label, in characters.
+const LABEL_LENGTH: usize = 64;
+
+/// Maximum length of a language code.
+const LANGUAGE_LENGTH: usize = 16;
+
 #[derive(CandidType, Deserialize)]
 pub struct PreparePayload {
-    user: String,
+    bot: u64,
+    proof: Proof,
     session: ByteBuf,
     canisters: Set<Principal>,
+    label: Option<String>,
+    expiration_minute: Option<u64>,
+    language: Option<String>,
+    profile: Option<String>,
+    origin: Option<String>,
 }
 
 impl PreparePayload {
-    pub fn user(&self) -> &str {
-        self.user.trim()
+    pub fn bot(&self) -> u64 {
+        self.bot
+    }
+
+    pub fn proof(&self) -> &Proof {
+        &self.proof
     }
 
     pub fn canisters(&self) -> Copied<impl Iterator<Item = &Principal>> {
@@ -23,13 +41,74 @@ impl PreparePayload {
         &self.session
     }
 
+    /// Device label shown when listing the user's sessions.
+    pub fn label(&self) -> Option<&str> {
+        self.label
+            .as_deref()
+            .map(str::trim)
+            .filter(|label| !label.is_empty())
+    }
+
+    /// Requested delegation lifetime, clamped to the bounds in the settings.
+    pub fn expiration_minute(&self) -> Option<u64> {
+        self.expiration_minute
+    }
+
+    /// Language code selecting the consent message template, e.g. Telegram's `language_code`.
+    pub fn language(&self) -> Option<&str> {
+        self.language
+            .as_deref()
+            .map(str::trim)
+            .filter(|language| !language.is_empty())
+    }
+
+    /// Profile the delegation is issued for; none selects the user's default principal.
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// Derivation origin of the frontend, selecting the application whose principal is used.
+    pub fn origin(&self) -> Option<&str> {
+        self.origin
+            .as_deref()
+            .map(|origin| origin.trim_end_matches('/'))
+    }
+
     pub fn validate(&self) -> Result<(), String> {
-        if self.user().is_empty() {
-            return Err(format!("User is empty"));
+        self.proof().validate()?;
+
+        if let Some(profile) = self.profile() {
+            validate_profile(profile)?;
+        }
+
+        if let Some(origin) = self.origin() {
+            validate_origin(origin)?;
+        }
+
+        if let Some(language) = self.language() {
+            let valid = language.len() <= LANGUAGE_LENGTH
+                && language
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+            if !valid {
+                return Err("Language code is invalid".to_owned());
+            }
+        }
+
+        if self.expiration_minute() == Some(0) {
+            return Err("Expiration minute must be positive".to_owned());
+        }
+
+        if self
+            .label()
+            .is_some_and(|label| label.chars().count() > LABEL_LENGTH)
+        {
+            return Err(format!("Label is longer than {LABEL_LENGTH} characters"));
         }
 
         if self.session().is_empty() {
-            return Err(format!("Session public key is empty"));
+            return Err("Session public key is empty".to_owned());
         }
 
         if let Err(e) = from_der(self.session()) {
@@ -38,27 +117,42 @@ impl PreparePayload {
 
         Ok(())
     }
-}
 
-impl Into<Message> for PreparePayload {
-    fn into(self) -> Message {
+    /// Scope of the given user of an isolated bot, in the selected profile and the application
+    /// of the origin; none for the user's default principal.
+    pub fn scope(&self, user: &str, bot: Option<u64>, application: Option<&str>) -> Option<Scope> {
+        Scope::new(user, bot, self.profile(), application)
+    }
+
+    /// Message for the given user string, which is the user string of the scope if any.
+    pub fn to_message(&self, user: &str) -> Message {
         Message::new(
-            self.user(),
+            user,
+            self.bot(),
             self.session(),
             self.canisters().chain([canister_principal()]),
+            self.expiration_minute(),
         )
+        .set_label(self.label())
+        .set_language(self.language())
     }
 }
 
 #[derive(CandidType, Deserialize)]
 pub struct LoginPayload {
     hash: Hash,
+    signature: Option<ByteBuf>,
 }
 
 impl LoginPayload {
     pub fn hash(&self) -> &Hash {
         &self.hash
     }
+
+    /// Signature over `hash` made with the session key of the prepared message.
+    pub fn signature(&self) -> Option<&[u8]> {
+        self.signature.as_deref().map(|signature| &signature[..])
+    }
 }
 
 #[derive(CandidType, Deserialize)]
@@ -70,6 +164,20 @@ pub struct DelegationPayload {
 }
 
 impl DelegationPayload {
+    pub(crate) fn new(
+        user: &str,
+        session: &[u8],
+        expiration: u64,
+        canisters: impl Iterator<Item = Principal>,
+    ) -> Self {
+        Self {
+            user: user.to_owned(),
+            session: ByteBuf::from(session),
+            expiration,
+            canisters: canisters.collect(),
+        }
+    }
+
     pub fn user(&self) -> &str {
         self.user.trim()
     }
@@ -88,11 +196,11 @@ impl DelegationPayload {
 
     pub fn validate(&self) -> Result<(), String> {
         if self.user().is_empty() {
-            return Err(format!("User is empty"));
+            return Err("User is empty".to_owned());
         }
 
         if self.session().is_empty() {
-            return Err(format!("Session public key is empty"));
+            return Err("Session public key is empty".to_owned());
         }
 
         if let Err(e) = from_der(self.session()) {
@@ -102,7 +210,7 @@ impl DelegationPayload {
         Ok(())
     }
 
-    pub fn into_delegation(&self) -> Result<Delegation, String> {
+    pub fn to_delegation(&self) -> Result<Delegation, String> {
         Delegation::new(
             self.user(),
             self.session(),
@@ -111,3 +219,12 @@ impl DelegationPayload {
         )
     }
 }
+
+/// Delegations to revoke: by delegation hash as returned by `login`, by session public key, or
+/// every delegation of every principal of a user.
+#[derive(CandidType, Deserialize)]
+pub enum RevokePayload {
+    Hash(Hash),
+    Session(ByteBuf),
+    User(String),
+}
@@ -0,0 +1,30 @@
+use crate::types::{ByteBuf, CandidType, Deserialize, Serialize};
+
+/// ICRC-21 `icrc21_consent_message_request`.
+#[derive(CandidType, Deserialize)]
+pub struct ConsentMessageRequest {
+    pub method: String,
+    pub arg: ByteBuf,
+    pub user_preferences: ConsentMessageSpec,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct ConsentMessageSpec {
+    pub metadata: ConsentMessageMetadata,
+    pub device_spec: Option<DeviceSpec>,
+}
+
+#[derive(CandidType, Clone, Deserialize, Serialize)]
+pub struct ConsentMessageMetadata {
+    pub language: String,
+    pub utc_offset_minutes: Option<i16>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub enum DeviceSpec {
+    GenericDisplay,
+    LineDisplay {
+        characters_per_line: u16,
+        lines_per_page: u16,
+    },
+}
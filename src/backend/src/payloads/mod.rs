@@ -1,7 +1,9 @@
 mod account;
 mod auth;
+mod icrc21;
 mod setting;
 
 pub(crate) use account::*;
-pub(crate) use auth::{DelegationPayload, LoginPayload, PreparePayload};
+pub(crate) use auth::{DelegationPayload, LoginPayload, PreparePayload, RevokePayload};
+pub(crate) use icrc21::*;
 pub(crate) use setting::SettingExtendsPayload;
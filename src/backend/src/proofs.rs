@@ -0,0 +1,108 @@
+use crate::types::*;
+
+/// Telegram proofs consumed by `prepare`, kept while their `auth_date` is within the proof window.
+pub struct Proofs {
+    hashes: StableBTreeMap<Hash, u64, VM>,
+    auth_dates: StableBTreeMap<(u64, Hash), (), VM>,
+}
+
+impl Proofs {
+    fn new(memories: [VM; 2]) -> Self {
+        let [m1, m2] = memories;
+
+        Self {
+            hashes: StableBTreeMap::init(m1),
+            auth_dates: StableBTreeMap::init(m2),
+        }
+    }
+
+    pub(crate) fn init(memories: [VM; 2]) -> RefCell<Self> {
+        RefCell::new(Self::new(memories))
+    }
+
+    pub fn prune(&mut self) -> usize {
+        let age = Setting::get().proof_expiration_minute().saturating_mul(60);
+
+        self.prune_at(utils::now_second(), age)
+    }
+
+    /// Forgets proofs whose `auth_date` is more than `age` seconds before `now`.
+    ///
+    /// The window is the one in force when pruning, not when the proof was consumed, so raising
+    /// it keeps every proof that is still remembered for as long as the new window accepts it.
+    fn prune_at(&mut self, now: u64, age: u64) -> usize {
+        let expired = self
+            .auth_dates
+            .iter()
+            .take_while(|((auth_date, _), _)| auth_date.saturating_add(age) < now)
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
+
+        for key in &expired {
+            self.auth_dates.remove(key);
+            self.hashes.remove(&key.1);
+        }
+
+        expired.len()
+    }
+
+    /// Records a proof consumed at `now`, with a window of `age` seconds, unless it is already
+    /// recorded.
+    pub fn insert(&mut self, hash: Hash, auth_date: u64, age: u64, now: u64) -> Result<(), String> {
+        self.prune_at(now, age);
+
+        if self.hashes.contains_key(&hash) {
+            return Err("Telegram proof has already been used".to_owned());
+        }
+
+        self.hashes.insert(hash, auth_date);
+        self.auth_dates.insert((auth_date, hash), ());
+
+        Ok(())
+    }
+
+    pub fn consume(hash: Hash, auth_date: u64, age: u64) -> Result<(), String> {
+        Self::borrow_mut(|proofs| proofs.insert(hash, auth_date, age, utils::now_second()))
+    }
+
+    pub fn borrow_mut<F: FnOnce(&mut Self) -> R, R>(f: F) -> R {
+        states::proofs::borrow_mut(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: Hash = [1; 32];
+
+    fn proofs() -> Proofs {
+        let manager = MemoryManager::init(DefaultMemoryImpl::default());
+
+        Proofs::new([manager.get(MemoryId::new(0)), manager.get(MemoryId::new(1))])
+    }
+
+    #[test]
+    fn proofs_are_pruned_once_out_of_the_window() {
+        let mut proofs = proofs();
+
+        assert!(proofs.insert(HASH, 1_000, 60, 1_000).is_ok());
+        assert_eq!(proofs.prune_at(1_060, 60), 0);
+        assert!(proofs.insert(HASH, 1_000, 60, 1_060).is_err());
+        assert_eq!(proofs.prune_at(1_061, 60), 1);
+        assert!(proofs.insert(HASH, 1_000, 60, 1_061).is_ok());
+    }
+
+    #[test]
+    fn raised_window_keeps_consumed_proofs() {
+        let mut proofs = proofs();
+
+        assert!(proofs.insert(HASH, 1_000, 60, 1_000).is_ok());
+        assert_eq!(proofs.prune_at(1_100, 3_600), 0);
+        assert_eq!(
+            proofs.insert(HASH, 1_000, 3_600, 4_600),
+            Err("Telegram proof has already been used".to_owned())
+        );
+        assert_eq!(proofs.prune_at(4_601, 3_600), 1);
+    }
+}
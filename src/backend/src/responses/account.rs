@@ -1,8 +1,63 @@
-use crate::types::{ByteBuf, CandidType, Principal, Serialize};
+use crate::types::{ByteBuf, CandidType, Deserialize, Hash, Principal, Scope, Serialize};
 
 #[cfg(feature = "ckbtc")]
 use crate::types::Map;
 
+/// Telegram identity behind a principal.
+#[derive(CandidType, Serialize)]
+pub struct Identity {
+    /// User string of the principal: the Telegram id, a legacy user string, or the user string
+    /// of a profile or application scope.
+    pub user: String,
+    pub id: Option<u64>,
+    pub username: Option<String>,
+    /// Bot the principal is isolated to; none for the identity shared by the other bots.
+    pub bot: Option<u64>,
+    /// Profile the principal belongs to; none for the user's default principal.
+    pub profile: Option<String>,
+    /// Application the principal is isolated to; none for the principal shared by every one.
+    pub application: Option<String>,
+    /// Whether the principal is derived with the deployment salt; legacy accounts are not.
+    pub salted: bool,
+}
+
+/// Records removed by `erase` and `forget`.
+#[derive(CandidType, Serialize)]
+pub struct Erased {
+    /// Principals of the user's accounts, profiles and applications.
+    pub principals: Vec<Principal>,
+    pub links: Vec<Principal>,
+    /// Delegations deleted, with their session keys and labels.
+    pub signatures: u64,
+    pub messages: u64,
+    pub username: bool,
+    pub alias: bool,
+}
+
+/// Account as exported by `exportAccounts` and imported by `importAccounts`.
+#[derive(CandidType, Deserialize, Serialize)]
+pub struct AccountRecord {
+    /// User string the principal is seeded on.
+    pub user: String,
+    pub principal: Principal,
+    pub salted: bool,
+    /// Telegram id aliased to this legacy account.
+    pub alias: Option<u64>,
+    pub username: Option<String>,
+    pub links: Vec<Principal>,
+    /// Profile and application the principal is seeded on, if not the user's default one.
+    pub scope: Option<Scope>,
+}
+
+/// Page of accounts. The deployment salt is only included in the first page.
+#[derive(CandidType, Serialize)]
+pub struct AccountsPage {
+    pub accounts: Vec<AccountRecord>,
+    pub salt: Option<ByteBuf>,
+    /// Start of the next page, if any.
+    pub next: Option<Hash>,
+}
+
 #[derive(CandidType, Serialize)]
 pub enum AccountDerivedAddressResponse {
     Ok(AccountDerivedAddress),
@@ -24,11 +79,116 @@ pub struct AccountDerivedBtcAddress {
     pub accounts: Map<Principal, Option<String>>,
 }
 
-impl Into<AccountDerivedAddressResponse> for Result<AccountDerivedAddress, String> {
-    fn into(self) -> AccountDerivedAddressResponse {
-        match self {
+impl From<Result<AccountDerivedAddress, String>> for AccountDerivedAddressResponse {
+    fn from(result: Result<AccountDerivedAddress, String>) -> Self {
+        match result {
             Ok(address) => AccountDerivedAddressResponse::Ok(address),
             Err(e) => AccountDerivedAddressResponse::Err(e),
         }
     }
 }
+
+#[derive(CandidType, Serialize)]
+pub enum EnableSaltResponse {
+    Ok(u64),
+    Err(String),
+}
+
+impl From<Result<u64, String>> for EnableSaltResponse {
+    fn from(result: Result<u64, String>) -> Self {
+        match result {
+            Ok(count) => EnableSaltResponse::Ok(count),
+            Err(e) => EnableSaltResponse::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize)]
+pub enum AliasResponse {
+    Ok(Principal),
+    Err(String),
+}
+
+impl From<Result<Principal, String>> for AliasResponse {
+    fn from(result: Result<Principal, String>) -> Self {
+        match result {
+            Ok(principal) => AliasResponse::Ok(principal),
+            Err(e) => AliasResponse::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize)]
+pub enum EraseResponse {
+    Ok(Erased),
+    Err(String),
+}
+
+impl From<Result<Erased, String>> for EraseResponse {
+    fn from(result: Result<Erased, String>) -> Self {
+        match result {
+            Ok(erased) => EraseResponse::Ok(erased),
+            Err(e) => EraseResponse::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize)]
+pub enum ImportAccountsResponse {
+    Ok(u64),
+    Err(String),
+}
+
+impl From<Result<u64, String>> for ImportAccountsResponse {
+    fn from(result: Result<u64, String>) -> Self {
+        match result {
+            Ok(count) => ImportAccountsResponse::Ok(count),
+            Err(e) => ImportAccountsResponse::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize)]
+pub enum LinkNonceResponse {
+    Ok(ByteBuf),
+    Err(String),
+}
+
+impl From<Result<ByteBuf, String>> for LinkNonceResponse {
+    fn from(result: Result<ByteBuf, String>) -> Self {
+        match result {
+            Ok(nonce) => LinkNonceResponse::Ok(nonce),
+            Err(e) => LinkNonceResponse::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize)]
+pub enum LinkResponse {
+    Ok(String),
+    Err(String),
+}
+
+impl From<Result<String, String>> for LinkResponse {
+    fn from(result: Result<String, String>) -> Self {
+        match result {
+            Ok(user) => LinkResponse::Ok(user),
+            Err(e) => LinkResponse::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize)]
+pub enum UnlinkResponse {
+    Ok,
+    Err(String),
+}
+
+impl From<Result<(), String>> for UnlinkResponse {
+    fn from(result: Result<(), String>) -> Self {
+        match result {
+            Ok(()) => UnlinkResponse::Ok,
+            Err(e) => UnlinkResponse::Err(e),
+        }
+    }
+}
@@ -1,20 +1,31 @@
-use crate::types::{ByteBuf, CandidType, Hash, Message, Principal, Serialize, Set, Timestamp};
+use crate::types::{
+    ByteBuf, CandidType, Format, Hash, Message, Principal, Serialize, Set, Setting, Signed,
+    Timestamp,
+};
 
 #[derive(CandidType, Clone, Serialize)]
 pub struct Prepared {
+    pub user: String,
     pub message: String,
+    pub markdown: String,
     pub expiration: u64,
     pub expired: Timestamp,
     pub hash: Hash,
+    pub expiration_minute: u64,
 }
 
 impl From<Message> for Prepared {
     fn from(message: Message) -> Self {
+        let setting = Setting::get();
+
         Prepared {
-            message: message.to_string(),
+            user: message.user().to_owned(),
+            message: message.render(&setting, Format::Plain),
+            markdown: message.render(&setting, Format::Markdown),
             expiration: message.expiration(),
             expired: message.expiration().into(),
             hash: message.hash(),
+            expiration_minute: message.expiration_minute().unwrap_or_default(),
         }
     }
 }
@@ -27,21 +38,59 @@ impl From<&Message> for Prepared {
 
 #[derive(CandidType, Clone, Serialize)]
 pub struct Login {
+    /// First delegation of the chain, when the principal belongs to a replaced canister.
+    pub bridge: Option<Bridge>,
+    pub expiration_minute: u64,
     pub expiration: u64,
     pub expired: Timestamp,
     pub canisters: Set<Principal>,
     pub hash: Hash,
 }
 
+/// Delegation from the user's principal key to this canister's key, issued by the canister
+/// that owns the principal. Fetch it from `canister` with `delegation`, passing `pubkey` as the
+/// session key, and put it before this canister's delegation in the chain.
+#[derive(CandidType, Clone, Serialize)]
+pub struct Bridge {
+    pub canister: Principal,
+    pub pubkey: ByteBuf,
+}
+
+/// Active delegation of a user, as listed by `sessions`.
+#[derive(CandidType, Clone, Serialize)]
+pub struct Session {
+    pub hash: Hash,
+    pub session: ByteBuf,
+    pub targets: Set<Principal>,
+    pub expiration: u64,
+    pub expired: Timestamp,
+    pub created: Timestamp,
+    pub label: Option<String>,
+}
+
+impl From<(Hash, Signed)> for Session {
+    fn from((hash, signed): (Hash, Signed)) -> Self {
+        Session {
+            hash,
+            session: ByteBuf::from(signed.session()),
+            targets: signed.targets().copied().collect(),
+            expiration: signed.expiration(),
+            expired: signed.expiration().into(),
+            created: signed.created().into(),
+            label: signed.label().map(str::to_owned),
+        }
+    }
+}
+
 #[derive(CandidType, Clone, Serialize)]
 pub enum PrepareResponse {
     Ok(Prepared),
     Err(String),
 }
 
-impl Into<PrepareResponse> for Result<Prepared, String> {
-    fn into(self) -> PrepareResponse {
-        match self {
+impl From<Result<Prepared, String>> for PrepareResponse {
+    fn from(result: Result<Prepared, String>) -> Self {
+        match result {
             Ok(prepared) => PrepareResponse::Ok(prepared),
             Err(err) => PrepareResponse::Err(err),
         }
@@ -54,15 +103,9 @@ pub enum LoginResponse {
     Err(String),
 }
 
-impl Into<Result<Login, String>> for Login {
-    fn into(self) -> Result<Login, String> {
-        Ok(self)
-    }
-}
-
-impl Into<LoginResponse> for Result<Login, String> {
-    fn into(self) -> LoginResponse {
-        match self {
+impl From<Result<Login, String>> for LoginResponse {
+    fn from(result: Result<Login, String>) -> Self {
+        match result {
             Ok(login) => LoginResponse::Ok(login),
             Err(err) => LoginResponse::Err(err),
         }
@@ -89,17 +132,32 @@ pub enum SignedDelegationResponse {
     Err(String),
 }
 
-impl Into<SignedDelegationResponse> for Result<SignedDelegation, String> {
-    fn into(self) -> SignedDelegationResponse {
-        match self {
+impl From<Result<SignedDelegation, String>> for SignedDelegationResponse {
+    fn from(result: Result<SignedDelegation, String>) -> Self {
+        match result {
             Ok(signed_delegation) => SignedDelegationResponse::Ok(signed_delegation),
             Err(err) => SignedDelegationResponse::Err(err),
         }
     }
 }
 
-impl Into<SignedDelegationResponse> for SignedDelegation {
-    fn into(self) -> SignedDelegationResponse {
-        SignedDelegationResponse::Ok(self)
+impl From<SignedDelegation> for SignedDelegationResponse {
+    fn from(signed_delegation: SignedDelegation) -> Self {
+        SignedDelegationResponse::Ok(signed_delegation)
+    }
+}
+
+#[derive(CandidType, Serialize)]
+pub enum BridgeResponse {
+    Ok(Hash),
+    Err(String),
+}
+
+impl From<Result<Hash, String>> for BridgeResponse {
+    fn from(result: Result<Hash, String>) -> Self {
+        match result {
+            Ok(hash) => BridgeResponse::Ok(hash),
+            Err(e) => BridgeResponse::Err(e),
+        }
     }
 }
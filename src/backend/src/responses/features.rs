@@ -0,0 +1,32 @@
+use crate::types::{CandidType, Map, Serialize, Verification};
+
+/// Entry of ICRC-10 `icrc10_supported_standards`.
+#[derive(CandidType, Serialize)]
+pub struct Standard {
+    pub name: String,
+    pub url: String,
+}
+
+/// Capabilities of a configured Telegram bot.
+#[derive(CandidType, Serialize)]
+pub struct BotFeatures {
+    pub id: u64,
+    pub verification: Verification,
+    pub public: bool,
+    /// Whether Login Widget data can be verified, which needs the bot token.
+    pub widget: bool,
+}
+
+/// Capability report returned by `features`.
+#[derive(CandidType, Serialize)]
+pub struct Features {
+    pub api_version: String,
+    /// Cargo features and whether they are compiled in.
+    pub cargo: Map<String, bool>,
+    /// Verification modes used by at least one configured bot.
+    pub verifications: Vec<Verification>,
+    pub bots: Vec<BotFeatures>,
+    pub standards: Vec<Standard>,
+    pub session_proof: bool,
+    pub target_allowlist: bool,
+}
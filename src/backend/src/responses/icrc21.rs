@@ -0,0 +1,67 @@
+use crate::payloads::ConsentMessageMetadata;
+use crate::types::{CandidType, Nat, Serialize};
+
+/// ICRC-21 `icrc21_consent_message`.
+#[derive(CandidType, Serialize)]
+pub enum ConsentMessage {
+    GenericDisplayMessage(String),
+    LineDisplayMessage { pages: Vec<LineDisplayPage> },
+}
+
+#[derive(CandidType, Serialize)]
+pub struct LineDisplayPage {
+    pub lines: Vec<String>,
+}
+
+#[derive(CandidType, Serialize)]
+pub struct ConsentInfo {
+    pub consent_message: ConsentMessage,
+    pub metadata: ConsentMessageMetadata,
+}
+
+#[derive(CandidType, Serialize)]
+pub struct ErrorInfo {
+    pub description: String,
+}
+
+/// ICRC-21 `icrc21_error`.
+#[allow(unused)]
+#[derive(CandidType, Serialize)]
+pub enum ConsentError {
+    UnsupportedCanisterCall(ErrorInfo),
+    ConsentMessageUnavailable(ErrorInfo),
+    InsufficientPayment(ErrorInfo),
+    GenericError {
+        error_code: Nat,
+        description: String,
+    },
+}
+
+impl ConsentError {
+    pub fn unsupported(description: impl Into<String>) -> Self {
+        Self::UnsupportedCanisterCall(ErrorInfo {
+            description: description.into(),
+        })
+    }
+
+    pub fn unavailable(description: impl Into<String>) -> Self {
+        Self::ConsentMessageUnavailable(ErrorInfo {
+            description: description.into(),
+        })
+    }
+}
+
+#[derive(CandidType, Serialize)]
+pub enum ConsentMessageResponse {
+    Ok(ConsentInfo),
+    Err(ConsentError),
+}
+
+impl From<Result<ConsentInfo, ConsentError>> for ConsentMessageResponse {
+    fn from(result: Result<ConsentInfo, ConsentError>) -> Self {
+        match result {
+            Ok(info) => ConsentMessageResponse::Ok(info),
+            Err(err) => ConsentMessageResponse::Err(err),
+        }
+    }
+}
@@ -1,9 +1,15 @@
 mod account;
 mod auth;
+mod features;
+mod icrc21;
+mod setting;
 
 pub(crate) use auth::{
-    Delegated, Login, LoginResponse, PrepareResponse, Prepared, SignedDelegation,
-    SignedDelegationResponse,
+    Bridge, BridgeResponse, Delegated, Login, LoginResponse, PrepareResponse, Prepared, Session,
+    SignedDelegation, SignedDelegationResponse,
 };
 
 pub(crate) use account::*;
+pub(crate) use features::*;
+pub(crate) use icrc21::*;
+pub(crate) use setting::*;
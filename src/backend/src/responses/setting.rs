@@ -0,0 +1,17 @@
+use crate::types::{CandidType, Serialize};
+
+/// Result of the setting updates that validate their input.
+#[derive(CandidType, Serialize)]
+pub enum SettingResponse {
+    Ok,
+    Err(String),
+}
+
+impl From<Result<(), String>> for SettingResponse {
+    fn from(result: Result<(), String>) -> Self {
+        match result {
+            Ok(()) => SettingResponse::Ok,
+            Err(e) => SettingResponse::Err(e),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use crate::types::{hash, Bound, CandidType, Cow, Deserialize, Hash, HashMap, Serialize, Storable};
+
+/// Principal of a user other than its default one: the user of an isolated bot, in a named profile, in an isolated application, or any combination of them.
+///
+/// A scope is stored under its user string, `Scope::name`, and its principal is seeded on its
+/// fields rather than on that string, so no scope can derive the principal of another scope or
+/// of an account seeded on a plain user string.
+#[derive(CandidType, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Scope {
+    user: String,
+    #[serde(default)]
+    bot: Option<u64>,
+    #[serde(default)]
+    profile: Option<String>,
+    #[serde(default)]
+    application: Option<String>,
+}
+
+impl Scope {
+    /// Scope of a user of a bot, in a profile and an application; none for the user's default
+    /// principal.
+    pub(crate) fn new(
+        user: &str,
+        bot: Option<u64>,
+        profile: Option<&str>,
+        application: Option<&str>,
+    ) -> Option<Self> {
+        if bot.is_none() && profile.is_none() && application.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            user: user.to_owned(),
+            bot,
+            profile: profile.map(str::to_owned),
+            application: application.map(str::to_owned),
+        })
+    }
+
+    /// User string of the user's default principal: its Telegram id or legacy user string.
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    /// Bot the principal is isolated to; none for bots that are not isolated.
+    pub fn bot(&self) -> Option<u64> {
+        self.bot
+    }
+
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    pub fn application(&self) -> Option<&str> {
+        self.application.as_deref()
+    }
+
+    /// User string the scope is known by in accounts, messages and delegations, e.g.
+    /// `12345?bot=777&profile=main&application=dex`. It is only ever looked up, never parsed.
+    pub(crate) fn name(&self) -> String {
+        let mut query = form_urlencoded::Serializer::new(String::new());
+
+        if let Some(bot) = self.bot {
+            query.append_pair("bot", &bot.to_string());
+        }
+
+        if let Some(profile) = &self.profile {
+            query.append_pair("profile", profile);
+        }
+
+        if let Some(application) = &self.application {
+            query.append_pair("application", application);
+        }
+
+        format!("{}?{}", self.user, query.finish())
+    }
+
+    /// Seed of the principal of the scope, mixed with the deployment salt if given.
+    pub(crate) fn seed(&self, salt: Option<&[u8]>) -> Hash {
+        let mut map = HashMap::from([("user", hash::Value::String(&self.user))]);
+
+        if let Some(bot) = self.bot {
+            map.insert("bot", hash::Value::U64(bot));
+        }
+
+        if let Some(profile) = &self.profile {
+            map.insert("profile", hash::Value::String(profile));
+        }
+
+        if let Some(application) = &self.application {
+            map.insert("application", hash::Value::String(application));
+        }
+
+        if let Some(salt) = salt {
+            map.insert("salt", hash::Value::Bytes(salt));
+        }
+
+        hash::domain(b"siwt-scoped-seed", &hash::map(map))
+    }
+}
+
+impl Storable for Scope {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        serde_cbor::to_vec(self).unwrap().into()
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        serde_cbor::from_slice(&bytes).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::utils;
+
+    #[test]
+    fn scope_seeds_are_separated_from_user_seeds() {
+        let scope = Scope::new("12345", None, Some("main"), None).unwrap();
+
+        assert_eq!(scope.name(), "12345?profile=main");
+        assert_ne!(scope.seed(None), utils::seed(&scope.name()));
+        assert_ne!(scope.seed(None), utils::seed("12345#main"));
+        assert_ne!(scope.seed(Some(b"salt")), scope.seed(None));
+    }
+
+    #[test]
+    fn isolated_bots_get_their_own_seeds() {
+        let one = Scope::new("12345", Some(1), None, None).unwrap();
+        let other = Scope::new("12345", Some(2), None, None).unwrap();
+
+        assert_eq!(one.name(), "12345?bot=1");
+        assert_ne!(one.seed(None), other.seed(None));
+        assert_ne!(one.seed(None), utils::seed("12345"));
+    }
+
+    #[test]
+    fn profile_and_application_seeds_differ() {
+        let profile = Scope::new("12345", None, Some("dex"), None).unwrap();
+        let application = Scope::new("12345", None, None, Some("dex")).unwrap();
+        let both = Scope::new("12345", None, Some("main"), Some("dex")).unwrap();
+
+        assert_ne!(profile.seed(None), application.seed(None));
+        assert_ne!(both.seed(None), application.seed(None));
+        assert_eq!(both.name(), "12345?profile=main&application=dex");
+        assert!(Scope::new("12345", None, None, None).is_none());
+    }
+}
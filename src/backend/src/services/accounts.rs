@@ -1,12 +1,203 @@
-use crate::payloads::AccountDerivedAddressPayload;
-use crate::responses::AccountDerivedAddress;
-use crate::types::{Accounts, Principal};
+use crate::accounts::{validate_application, validate_profile};
+use crate::payloads::{AccountDerivedAddressPayload, ImportAccountsPayload};
+use crate::responses::{AccountDerivedAddress, AccountsPage, Erased};
+use crate::types::{
+    caller_principal, utils, Accounts, ByteBuf, Hash, Messages, Principal, Scope, Setting, State,
+};
+use ic_cdk::api::management_canister::main::raw_rand;
+
+/// Time a link nonce can be used, in minutes.
+const LINK_EXPIRATION_MINUTE: u64 = 10;
+
+/// Public key of the principal of the payload user, which may be a Telegram id, a username or
+/// a legacy user, for the selected bot with the selected profile in the selected application.
+fn public_key(payload: &AccountDerivedAddressPayload) -> Result<ByteBuf, String> {
+    let profile = payload.profile.as_deref();
+    let application = payload.application.as_deref();
+
+    if let Some(profile) = profile {
+        validate_profile(profile)?;
+    }
+
+    if let Some(application) = application {
+        validate_application(application)?;
+    }
+
+    let bot = payload.bot.and_then(|bot| Setting::get().isolated_bot(bot));
+    let seed = Accounts::borrow(|accounts| {
+        let user = accounts.resolve(&payload.user)?;
+
+        Some(match Scope::new(&user, bot, profile, application) {
+            Some(scope) => accounts.scope_seed(&scope),
+            None => accounts.seed_of(&user),
+        })
+    })
+    .ok_or(format!("User {} not found", payload.user))?;
+
+    Accounts::canister_public_key(&Setting::get().signing_canister(), &seed)
+}
+
+pub async fn enable_salt() -> Result<u64, String> {
+    if Accounts::borrow(Accounts::has_salt) {
+        return Err("Salt is already set".to_owned());
+    }
+
+    let (salt,) = raw_rand()
+        .await
+        .map_err(|(code, message)| format!("Failed to generate salt: {:?} {}", code, message))?;
+
+    Accounts::borrow_mut(|accounts| accounts.set_salt(salt)).map(|count| count as u64)
+}
+
+/// Issues a nonce with which a second principal can link itself to the caller's account.
+pub async fn link_nonce() -> Result<ByteBuf, String> {
+    let caller = caller_principal();
+    let user = Accounts::borrow(|accounts| accounts.user(&caller))
+        .ok_or("Caller is not a signed-in user".to_owned())?;
+
+    let (nonce,) = raw_rand()
+        .await
+        .map_err(|(code, message)| format!("Failed to generate nonce: {:?} {}", code, message))?;
+    let expiration = utils::now_add_minute(LINK_EXPIRATION_MINUTE);
+
+    Accounts::borrow_mut(|accounts| accounts.insert_nonce(&nonce, &user, expiration));
+
+    Ok(ByteBuf::from(nonce))
+}
+
+/// Links the caller to the user a nonce was issued for.
+pub async fn link(nonce: ByteBuf) -> Result<String, String> {
+    let caller = caller_principal();
+
+    Accounts::borrow_mut(|accounts| {
+        let user = accounts
+            .take_nonce(&nonce)
+            .ok_or("Link nonce not found or expired".to_owned())?;
+
+        accounts.link(caller, &user)?;
+
+        Ok(user)
+    })
+}
+
+/// Unlinks a principal. The linked principal itself, the account principal of its user and
+/// authorities may unlink it.
+pub async fn unlink(principal: Principal) -> Result<(), String> {
+    let caller = caller_principal();
+    let authorized = Setting::get().authorized(&caller);
+
+    Accounts::borrow_mut(|accounts| {
+        let user = accounts
+            .owner(&principal)
+            .filter(|_| accounts.user(&principal).is_none())
+            .ok_or("Principal is not linked".to_owned())?;
+
+        if caller != principal && accounts.user(&caller) != Some(user) && !authorized {
+            return Err("Unauthorized".to_owned());
+        }
+
+        accounts.unlink(&principal);
+
+        Ok(())
+    })
+}
+
+/// Erases a user with every account, delegation and pending message of it.
+fn erase_user(user: &str) -> Erased {
+    let erasure = Accounts::borrow_mut(|accounts| accounts.erase(user));
+
+    let signatures = State::with(|state| {
+        let signatures = &mut *state.signatures().borrow_mut();
+        let deleted = erasure
+            .seeds
+            .iter()
+            .map(|seed| signatures.delete_seed(*seed))
+            .sum::<usize>();
+
+        state.update_root_hash(signatures);
+
+        deleted
+    });
+    let messages = Messages::borrow_mut(|messages| {
+        erasure
+            .users
+            .iter()
+            .map(|user| messages.remove_user(user))
+            .sum::<usize>()
+    });
+
+    Erased {
+        principals: erasure.principals,
+        links: erasure.links,
+        signatures: signatures as u64,
+        messages: messages as u64,
+        username: erasure.username,
+        alias: erasure.alias,
+    }
+}
+
+/// Erases a user given by Telegram id, username or legacy user string.
+pub async fn erase(user: String) -> Result<Erased, String> {
+    let user = Accounts::borrow(|accounts| accounts.resolve(&user))
+        .ok_or(format!("User {} not found", user))?;
+
+    Ok(erase_user(&user))
+}
+
+/// Erases the caller's user, with every profile and application principal of it.
+pub async fn forget() -> Result<Erased, String> {
+    if !Setting::get().self_erasure() {
+        return Err("Self-service erasure is disabled".to_owned());
+    }
+
+    let caller = caller_principal();
+    let user = Accounts::borrow(|accounts| {
+        let user = accounts.user(&caller)?;
+
+        Some(
+            accounts
+                .scope(&user)
+                .map_or(user, |scope| scope.user().to_owned()),
+        )
+    })
+    .ok_or("Caller is not a signed-in user".to_owned())?;
+
+    Ok(erase_user(&user))
+}
+
+/// Largest page returned by `export`.
+const EXPORT_LIMIT: u64 = 1000;
+
+pub async fn export(start: Option<Hash>, limit: u64) -> AccountsPage {
+    let limit = limit.clamp(1, EXPORT_LIMIT) as usize;
+
+    Accounts::borrow(|accounts| {
+        let (records, next) = accounts.export(start, limit);
+        let salt = match (start, accounts.has_salt()) {
+            (None, true) => Some(ByteBuf::from(accounts.salt())),
+            _ => None,
+        };
+
+        AccountsPage {
+            accounts: records,
+            salt,
+            next,
+        }
+    })
+}
+
+pub async fn import(payload: ImportAccountsPayload) -> Result<u64, String> {
+    let canister = Setting::get().signing_canister();
+    let salt = payload.salt.map(ByteBuf::into_vec);
+
+    Accounts::borrow_mut(|accounts| accounts.import(&canister, payload.accounts, salt))
+}
 
 #[cfg(not(feature = "ckbtc"))]
 pub async fn derived(
     payload: AccountDerivedAddressPayload,
 ) -> Result<AccountDerivedAddress, String> {
-    let pubkey = Accounts::user_canister_public_key(&payload.user)?;
+    let pubkey = public_key(&payload)?;
     let principal = Principal::self_authenticating(&pubkey);
 
     Ok(AccountDerivedAddress { pubkey, principal })
@@ -20,7 +211,7 @@ pub async fn derived(
     use crate::services::ckbtc;
     use futures::future::join_all;
 
-    let pubkey = Accounts::user_canister_public_key(&payload.user)?;
+    let pubkey = public_key(&payload)?;
     let principal = Principal::self_authenticating(&pubkey);
 
     let address = ckbtc::owned(principal);
@@ -42,7 +233,7 @@ pub async fn derived(
             address: address?,
             accounts: accounts
                 .into_iter()
-                .map(|(owner, address)| (owner, address.map_or(None, Some)))
+                .map(|(owner, address)| (owner, address.ok()))
                 .collect(),
         },
     })
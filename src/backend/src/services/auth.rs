@@ -1,11 +1,113 @@
-use crate::payloads::{DelegationPayload, LoginPayload, PreparePayload};
-use crate::responses::{Login, Prepared, SignedDelegation};
-use crate::types::{ByteBuf, Delegation, Message, Messages, Setting};
+use crate::payloads::{DelegationPayload, LoginPayload, PreparePayload, RevokePayload};
+use crate::responses::{Bridge, Login, Prepared, Session, SignedDelegation};
+use crate::session;
+use crate::telegram::Verified;
+use crate::types::{
+    caller_principal, utils, Accounts, ByteBuf, Delegation, Hash, InitData, Messages, Proof,
+    Proofs, Scope, Setting, State, Verification,
+};
+
+/// Seed hashes of every principal of a Telegram id, a username or a legacy user string: its
+/// default principal and each of its bot, profile and application principals.
+fn seed_hashes(user: &str) -> Vec<Hash> {
+    Accounts::borrow(|accounts| {
+        let user = accounts.resolve(user).unwrap_or(user.to_owned());
+
+        accounts.seed_hashes(&user)
+    })
+}
+
+/// Tolerated clock skew, in seconds, for an `auth_date` ahead of the canister time.
+const AUTH_DATE_SKEW: u64 = 60;
+
+/// Rejects callers that are neither authorized nor allowed to log in publicly with the bot.
+fn permit(setting: &Setting, bot: u64) -> Result<(), String> {
+    if setting.permits(&caller_principal(), bot) {
+        return Ok(());
+    }
+
+    Err("Unauthorized".to_owned())
+}
+
+/// Verifies a Telegram proof with the policy of the given bot.
+pub(crate) fn verify(setting: &Setting, id: u64, proof: &Proof) -> Result<Verified, String> {
+    let bot = setting.bot(id)?;
+
+    match proof {
+        Proof::InitData(init_data) => {
+            let init_data = InitData::parse(init_data)?;
+            let hash = match bot.verification() {
+                Verification::Token => init_data.verify(bot.token()?)?,
+                Verification::Signature => init_data.verify_signature(id, setting.public_keys())?,
+            };
+
+            Ok(Verified::new(
+                init_data.user()?,
+                init_data.auth_date()?,
+                hash,
+            ))
+        }
+        Proof::Widget(widget) => {
+            let hash = widget.verify(bot.token()?)?;
+
+            Ok(Verified::new(widget.user(), widget.auth_date(), hash))
+        }
+    }
+}
+
+/// Rejects proofs whose `auth_date` is outside the freshness window.
+fn check_freshness(setting: &Setting, verified: &Verified) -> Result<(), String> {
+    let now = utils::now_second();
+    let age = setting.proof_expiration_minute().saturating_mul(60);
+    let auth_date = verified.auth_date();
+
+    if auth_date > now.saturating_add(AUTH_DATE_SKEW) {
+        return Err("Telegram proof auth date is in the future".to_owned());
+    }
+
+    if now.saturating_sub(auth_date) > age || auth_date < setting.proof_not_before() {
+        return Err("Telegram proof is expired".to_owned());
+    }
+
+    Ok(())
+}
 
 pub async fn prepare(payload: PreparePayload) -> Result<Prepared, String> {
     payload.validate()?;
 
-    let message: Message = payload.into();
+    let setting = Setting::get();
+
+    permit(&setting, payload.bot())?;
+
+    setting.check_targets(payload.bot(), payload.canisters())?;
+
+    let application =
+        setting.application_of(payload.origin(), payload.bot(), payload.canisters())?;
+
+    let verified = verify(&setting, payload.bot(), payload.proof())?;
+
+    check_freshness(&setting, &verified)?;
+
+    let id = verified.user().id();
+    let user = Accounts::borrow_mut(|accounts| {
+        let user = accounts.seed_user(id);
+
+        match payload.scope(&user, setting.isolated_bot(payload.bot()), application) {
+            Some(scope) => accounts.register(&scope),
+            None => Ok(user),
+        }
+    })?;
+
+    // Consumed last, so a proof is only used up by a `prepare` that succeeds.
+    Proofs::consume(
+        verified.hash(),
+        verified.auth_date(),
+        setting.proof_expiration_minute().saturating_mul(60),
+    )?;
+
+    Accounts::borrow_mut(|accounts| accounts.set_username(id, verified.user().username()));
+
+    let message = payload.to_message(&user);
 
     Messages::put(&message);
 
@@ -15,28 +117,168 @@ pub async fn prepare(payload: PreparePayload) -> Result<Prepared, String> {
 }
 
 pub async fn login(payload: LoginPayload) -> Result<Login, String> {
-    let message = Messages::delete(payload.hash()).ok_or(format!("Hash not found or expired"))?;
     let setting = Setting::get();
+    let message = Messages::get(payload.hash()).ok_or("Hash not found or expired".to_owned())?;
+
+    permit(&setting, message.bot())?;
+
+    match payload.signature() {
+        Some(signature) => session::verify(message.session(), payload.hash(), signature)?,
+        None if setting.session_proof() => {
+            return Err("Session signature is required".to_owned());
+        }
+        None => {}
+    }
+
+    let minute = message
+        .expiration_minute()
+        .unwrap_or_else(|| setting.expiration_minute_of(message.bot(), message.canisters(), None));
     let delegation = Delegation::new(
         message.user(),
         message.session(),
-        setting.expiration(),
+        utils::now_add_minute(minute),
         message.canisters().copied(),
     )?;
 
+    // Taken before awaiting the bridge, so the message cannot be used twice meanwhile.
+    Messages::delete(payload.hash());
+
+    let bridge = match setting.migrated() {
+        true => match request_bridge(&setting, &delegation).await {
+            Ok(bridge) => Some(bridge),
+            Err(e) => {
+                // Put the message back, so the user can retry without a new Telegram proof.
+                Messages::borrow_mut(|messages| messages.insert(message));
+
+                return Err(e);
+            }
+        },
+        false => None,
+    };
+
     Ok(Login {
+        bridge,
+        expiration_minute: minute,
         expiration: delegation.expiration(),
         expired: delegation.expiration().into(),
         canisters: delegation.targets().copied().collect(),
-        hash: delegation.store(),
+        hash: delegation.store(message.label()),
+    })
+}
+
+/// Asks the canister that owns the user's principal to delegate from it to this canister's key
+/// for the user, with the lifetime and targets of the given delegation.
+async fn request_bridge(setting: &Setting, delegation: &Delegation) -> Result<Bridge, String> {
+    let canister = setting.signing_canister();
+    let payload = DelegationPayload::new(
+        delegation.user(),
+        delegation.signer(),
+        delegation.expiration(),
+        delegation.targets().copied(),
+    );
+
+    let scope = Accounts::borrow(|accounts| accounts.scope(delegation.user()));
+
+    let (result,): (Result<Hash, String>,) = ic_cdk::call(canister, "bridge", (payload, scope))
+        .await
+        .map_err(|(code, message)| format!("Failed to request bridge: {:?} {}", code, message))?;
+
+    result.map_err(|e| format!("Bridge rejected: {}", e))?;
+
+    Ok(Bridge {
+        canister,
+        pubkey: ByteBuf::from(delegation.signer()),
     })
 }
 
+/// Delegates from a user's principal key to the key of the successor canister for the same
+/// user, so the successor can keep issuing delegations for the principal. The scope of a
+/// profile or application user string is registered first, as the user may not have logged in
+/// with it before the migration.
+pub async fn bridge(payload: DelegationPayload, scope: Option<Scope>) -> Result<Hash, String> {
+    payload.validate()?;
+
+    let successor = Setting::get()
+        .successor()
+        .filter(|successor| *successor == caller_principal())
+        .ok_or("Unauthorized".to_owned())?;
+
+    if let Some(scope) = scope {
+        if scope.name() != payload.user() {
+            return Err("Scope does not match the user".to_owned());
+        }
+
+        Accounts::borrow_mut(|accounts| accounts.register(&scope))?;
+    }
+
+    let pubkey = Accounts::canister_public_key(&successor, &Accounts::seed(payload.user()))?;
+
+    if payload.session() != pubkey.as_slice() {
+        return Err("Session is not the successor key of the user".to_owned());
+    }
+
+    if payload.expiration() <= utils::now() {
+        return Err("Expiration is in the past".to_owned());
+    }
+
+    Ok(payload.to_delegation()?.store(Some("bridge")))
+}
+
 pub async fn delegation(
     certificate: ByteBuf,
     payload: DelegationPayload,
 ) -> Result<SignedDelegation, String> {
     payload.validate()?;
 
-    payload.into_delegation()?.sign(certificate)
+    payload.to_delegation()?.sign(certificate)
+}
+
+/// Revokes the matching delegations and returns how many were revoked.
+pub async fn revoke(payload: RevokePayload) -> u64 {
+    State::with(|state| {
+        let signatures = &mut *state.signatures().borrow_mut();
+
+        let revoked = match payload {
+            RevokePayload::Hash(hash) => signatures.revoke_hash(hash),
+            RevokePayload::Session(session) => signatures.revoke_session(&session),
+            RevokePayload::User(user) => seed_hashes(&user)
+                .into_iter()
+                .map(|seed| signatures.revoke_seed(seed))
+                .sum(),
+        };
+
+        state.update_root_hash(signatures);
+
+        revoked as u64
+    })
+}
+
+/// Unexpired prepared messages, overall or for every principal of a user.
+pub async fn pending_messages(user: Option<String>) -> u64 {
+    let Some(user) = user else {
+        return Messages::borrow(Messages::pending);
+    };
+    let users = Accounts::borrow(|accounts| {
+        let user = accounts.resolve(&user).unwrap_or(user);
+
+        accounts.owned(&user)
+    });
+
+    Messages::borrow(|messages| users.iter().map(|user| messages.pending_user(user)).sum())
+}
+
+/// Active delegations of every principal of a user, oldest first.
+pub async fn sessions(user: String) -> Vec<Session> {
+    State::with(|state| {
+        let signatures = state.signatures().borrow();
+        let mut sessions = seed_hashes(&user)
+            .into_iter()
+            .flat_map(|seed| signatures.sessions(seed))
+            .map(Session::from)
+            .collect::<Vec<_>>();
+
+        sessions.sort_by_key(|session| session.created);
+
+        sessions
+    })
 }
@@ -0,0 +1,55 @@
+use crate::responses::{BotFeatures, Features, Standard};
+use crate::types::{Map, Setting, Verification};
+
+/// Version of the canister API, bumped with breaking interface changes.
+const API_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// ICRC standards implemented by this canister.
+const STANDARDS: [(&str, &str); 2] = [
+    (
+        "ICRC-10",
+        "https://github.com/dfinity/ICRC/blob/main/ICRCs/ICRC-10/ICRC-10.md",
+    ),
+    (
+        "ICRC-21",
+        "https://github.com/dfinity/wg-identity-authentication/blob/main/topics/ICRC-21/icrc_21_consent_msg.md",
+    ),
+];
+
+pub fn standards() -> Vec<Standard> {
+    STANDARDS
+        .into_iter()
+        .map(|(name, url)| Standard {
+            name: name.to_owned(),
+            url: url.to_owned(),
+        })
+        .collect()
+}
+
+pub async fn features() -> Features {
+    let setting = Setting::get();
+    let bots = setting
+        .bots()
+        .map(|(id, bot)| BotFeatures {
+            id: *id,
+            verification: bot.verification(),
+            public: bot.public(),
+            widget: bot.token().is_ok(),
+        })
+        .collect::<Vec<_>>();
+    let verifications = [Verification::Token, Verification::Signature]
+        .into_iter()
+        .filter(|verification| bots.iter().any(|bot| bot.verification == *verification))
+        .collect();
+    let cargo = Map::from([("ckbtc".to_owned(), cfg!(feature = "ckbtc"))]);
+
+    Features {
+        api_version: API_VERSION.to_owned(),
+        cargo,
+        verifications,
+        bots,
+        standards: standards(),
+        session_proof: setting.session_proof(),
+        target_allowlist: setting.target_allowlist(),
+    }
+}
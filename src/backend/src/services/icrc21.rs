@@ -0,0 +1,389 @@
+use crate::payloads::{
+    ConsentMessageMetadata, ConsentMessageRequest, DeviceSpec, ImportAccountsPayload, LoginPayload,
+    PreparePayload, RevokePayload, SettingExtendsPayload,
+};
+use crate::responses::{ConsentError, ConsentInfo, ConsentMessage, LineDisplayPage};
+use crate::services::auth;
+use crate::types::{
+    Accounts, Application, Bot, ByteBuf, Format, Messages, Principal, Set, Setting, Target,
+    Verification,
+};
+use candid::utils::ArgumentDecoder;
+
+/// Decodes the candid arguments of the call a consent message is requested for.
+fn decode<T: for<'a> ArgumentDecoder<'a>>(arg: &[u8]) -> Result<T, ConsentError> {
+    candid::decode_args(arg)
+        .map_err(|e| ConsentError::unsupported(format!("Invalid arguments: {}", e)))
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "Require"
+    } else {
+        "Do not require"
+    }
+}
+
+fn minutes(minute: Option<u64>) -> String {
+    minute.map_or("the default".to_owned(), |minute| {
+        format!("{minute} minutes")
+    })
+}
+
+fn principals(principals: &Set<Principal>) -> String {
+    principals
+        .iter()
+        .map(Principal::to_text)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Consent text for a call, with the language it is written in.
+///
+/// `prepare` and `login` are rendered from the prepared `Message` with the template for the
+/// requested language; the administrative methods are described in English.
+fn text(
+    setting: &Setting,
+    method: &str,
+    arg: &[u8],
+    language: &str,
+) -> Result<(String, String), ConsentError> {
+    let english = |text: String| Ok(("en".to_owned(), text));
+
+    match method {
+        "prepare" => {
+            let (payload,): (PreparePayload,) = decode(arg)?;
+
+            payload.validate().map_err(ConsentError::unavailable)?;
+
+            let verified = auth::verify(setting, payload.bot(), payload.proof())
+                .map_err(ConsentError::unavailable)?;
+            let application = setting
+                .application_of(payload.origin(), payload.bot(), payload.canisters())
+                .map_err(ConsentError::unavailable)?;
+            let user = Accounts::borrow(|accounts| accounts.seed_user(verified.user().id()));
+            let user = payload
+                .scope(&user, setting.isolated_bot(payload.bot()), application)
+                .map_or(user, |scope| scope.name());
+            let message = payload.to_message(&user).set_language(Some(language));
+            let (language, _) = setting.template(message.language());
+
+            Ok((language.to_owned(), message.render(setting, Format::Plain)))
+        }
+        "login" => {
+            let (payload,): (LoginPayload,) = decode(arg)?;
+            let message = Messages::get(payload.hash())
+                .ok_or(ConsentError::unavailable("Hash not found or expired"))?
+                .set_language(Some(language));
+            let (language, _) = setting.template(message.language());
+
+            Ok((language.to_owned(), message.render(setting, Format::Plain)))
+        }
+        "globalsStore" => {
+            let (key, value): (String, ByteBuf) = decode(arg)?;
+
+            english(format!(
+                "Store {} bytes under the global key {key}.",
+                value.len()
+            ))
+        }
+        "extends" => {
+            let (payload,): (SettingExtendsPayload,) = decode(arg)?;
+
+            english(format!(
+                "Add the authorities [{}] and the canisters [{}] to the settings.",
+                principals(&payload.authorities),
+                principals(&payload.canisters)
+            ))
+        }
+        "setExpirationMinute" => {
+            let (minute,): (u64,) = decode(arg)?;
+
+            english(format!(
+                "Set the delegation expiration to {minute} minutes."
+            ))
+        }
+        "setExpirationBounds" => {
+            let (min, max): (Option<u64>, Option<u64>) = decode(arg)?;
+
+            english(format!(
+                "Allow requested delegation expirations from {} to {}.",
+                minutes(min),
+                minutes(max)
+            ))
+        }
+        "setBot" => {
+            let (id, bot): (u64, Bot) = decode(arg)?;
+            let verification = match bot.verification() {
+                Verification::Token => "bot token",
+                Verification::Signature => "Telegram signature",
+            };
+            let public = if bot.public() {
+                ", open to public login"
+            } else {
+                ""
+            };
+            let isolated = if bot.isolated() {
+                ", giving every user a principal of its own for this bot"
+            } else {
+                ""
+            };
+
+            english(format!(
+                "Configure Telegram bot {id} with {verification} verification{public}{isolated}."
+            ))
+        }
+        "removeBot" => {
+            let (id,): (u64,) = decode(arg)?;
+
+            english(format!("Remove Telegram bot {id}."))
+        }
+        "setTarget" => {
+            let (canister, target): (Principal, Target) = decode(arg)?;
+            let name = target.name().unwrap_or("canister");
+            let default = if target.default() {
+                " and grant it in every delegation"
+            } else {
+                ""
+            };
+
+            english(format!(
+                "Allow delegations to {name} {} for at most {}{default}.",
+                canister.to_text(),
+                minutes(target.expiration_minute())
+            ))
+        }
+        "removeTarget" => {
+            let (canister,): (Principal,) = decode(arg)?;
+
+            english(format!(
+                "Remove {} from the allowed delegation targets.",
+                canister.to_text()
+            ))
+        }
+        "setApplication" => {
+            let (id, application): (String, Application) = decode(arg)?;
+            let name = application.name().unwrap_or(&id);
+
+            english(format!(
+                "Give users separate principals in application {name}, used from the origins {} with the bots {} for the canisters [{}].",
+                application.origins().collect::<Vec<_>>().join(", "),
+                application
+                    .bots()
+                    .map(u64::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                principals(&application.canisters().copied().collect())
+            ))
+        }
+        "removeApplication" => {
+            let (id,): (String,) = decode(arg)?;
+
+            english(format!(
+                "Remove application {id}. Its origins can no longer sign in until it is registered again."
+            ))
+        }
+        "setSigningCanister" => {
+            let (canister,): (Option<Principal>,) = decode(arg)?;
+
+            english(match canister {
+                Some(canister) => format!(
+                    "Keep serving the user principals of canister {}.",
+                    canister.to_text()
+                ),
+                None => "Serve user principals of this canister.".to_owned(),
+            })
+        }
+        "setSuccessor" => {
+            let (canister,): (Option<Principal>,) = decode(arg)?;
+
+            english(match canister {
+                Some(canister) => format!(
+                    "Let canister {} issue delegations for the user principals of this canister.",
+                    canister.to_text()
+                ),
+                None => "Stop bridging delegations to a successor canister.".to_owned(),
+            })
+        }
+        "erase" => {
+            let (user,): (String,) = decode(arg)?;
+
+            english(format!(
+                "Erase every account, session and pending sign-in of user {user}. This cannot be undone."
+            ))
+        }
+        "forget" => english(
+            "Erase your accounts, profiles, linked principals, sessions and pending sign-ins. This cannot be undone."
+                .to_owned(),
+        ),
+        "setSelfErasure" => {
+            let (enabled,): (bool,) = decode(arg)?;
+
+            english(match enabled {
+                true => "Let users erase their own data.".to_owned(),
+                false => "Stop users from erasing their own data.".to_owned(),
+            })
+        }
+        "importAccounts" => {
+            let (payload,): (ImportAccountsPayload,) = decode(arg)?;
+
+            english(format!("Import {} accounts.", payload.accounts.len()))
+        }
+        "setTemplate" => {
+            let (language, template): (String, Option<String>) = decode(arg)?;
+
+            english(match template {
+                Some(template) => {
+                    format!("Set the consent message template for {language} to: {template}")
+                }
+                None => format!("Remove the consent message template for {language}."),
+            })
+        }
+        "setPublicKeys" => {
+            let (keys,): (Option<Set<ByteBuf>>,) = decode(arg)?;
+
+            english(match keys {
+                Some(keys) => format!("Accept {} Telegram public keys.", keys.len()),
+                None => "Accept Telegram's production public key.".to_owned(),
+            })
+        }
+        "setProofExpirationMinute" => {
+            let (minute,): (u64,) = decode(arg)?;
+
+            english(format!(
+                "Accept Telegram proofs up to {minute} minutes old."
+            ))
+        }
+        "setSessionProof" => {
+            let (required,): (bool,) = decode(arg)?;
+
+            english(format!(
+                "{} a session key signature in login.",
+                on_off(required)
+            ))
+        }
+        "setMessageExpirationMinute" => {
+            let (minute,): (u64,) = decode(arg)?;
+
+            english(format!("Keep prepared messages for {minute} minutes."))
+        }
+        "setMessageLimits" => {
+            let (max, max_user): (Option<u64>, Option<u64>) = decode(arg)?;
+            let limit = |limit: Option<u64>| limit.map_or("default".to_owned(), |l| l.to_string());
+
+            english(format!(
+                "Limit pending messages to {} overall and {} per user.",
+                limit(max),
+                limit(max_user)
+            ))
+        }
+        "enableSalt" => english(
+            "Generate a secret salt for the principals of new accounts. This cannot be undone."
+                .to_owned(),
+        ),
+        "linkNonce" => english(
+            "Create a code to link another principal to your account.".to_owned(),
+        ),
+        "link" => english(
+            "Link this principal to the account that created the code. The account will be able to identify it as yours."
+                .to_owned(),
+        ),
+        "unlink" => {
+            let (principal,): (Principal,) = decode(arg)?;
+
+            english(format!(
+                "Unlink {} from its account.",
+                principal.to_text()
+            ))
+        }
+        "alias" => {
+            let (legacy, id): (String, u64) = decode(arg)?;
+
+            english(format!(
+                "Let Telegram user {id} sign in to the legacy account {legacy}."
+            ))
+        }
+        "revoke" => {
+            let (payload,): (RevokePayload,) = decode(arg)?;
+
+            english(match payload {
+                RevokePayload::Hash(hash) => format!("Revoke delegation {}.", hex::encode(hash)),
+                RevokePayload::Session(_) => "Revoke every delegation of a session key.".to_owned(),
+                RevokePayload::User(user) => format!("Revoke every delegation of user {user}."),
+            })
+        }
+        _ => Err(ConsentError::unsupported(format!(
+            "No consent message for method {method}"
+        ))),
+    }
+}
+
+/// Splits a text into pages of at most `lines_per_page` lines of `characters_per_line`
+/// characters, breaking at spaces where possible.
+fn paginate(text: &str, characters_per_line: usize, lines_per_page: usize) -> Vec<LineDisplayPage> {
+    let width = characters_per_line.max(1);
+    let mut lines = Vec::new();
+
+    for paragraph in text.lines() {
+        let mut line = String::new();
+
+        for word in paragraph.split_whitespace() {
+            let mut word = word.chars().collect::<Vec<_>>();
+
+            while !word.is_empty() {
+                let length = line.chars().count();
+                let space = usize::from(length > 0);
+
+                if length + space + word.len() <= width {
+                    if space > 0 {
+                        line.push(' ');
+                    }
+                    line.extend(word.drain(..));
+                } else if length > 0 {
+                    lines.push(std::mem::take(&mut line));
+                } else {
+                    line.extend(word.drain(..width));
+                    lines.push(std::mem::take(&mut line));
+                }
+            }
+        }
+
+        lines.push(line);
+    }
+
+    lines
+        .chunks(lines_per_page.max(1))
+        .map(|lines| LineDisplayPage {
+            lines: lines.to_vec(),
+        })
+        .collect()
+}
+
+pub async fn consent(request: ConsentMessageRequest) -> Result<ConsentInfo, ConsentError> {
+    let setting = Setting::get();
+    let preferences = request.user_preferences;
+    let (language, text) = text(
+        &setting,
+        &request.method,
+        &request.arg,
+        &preferences.metadata.language,
+    )?;
+
+    let consent_message = match preferences.device_spec {
+        Some(DeviceSpec::LineDisplay {
+            characters_per_line,
+            lines_per_page,
+        }) => ConsentMessage::LineDisplayMessage {
+            pages: paginate(&text, characters_per_line.into(), lines_per_page.into()),
+        },
+        Some(DeviceSpec::GenericDisplay) | None => ConsentMessage::GenericDisplayMessage(text),
+    };
+
+    Ok(ConsentInfo {
+        consent_message,
+        metadata: ConsentMessageMetadata {
+            language,
+            utc_offset_minutes: None,
+        },
+    })
+}
@@ -1,6 +1,8 @@
 pub(crate) mod accounts;
 pub(crate) mod auth;
+pub(crate) mod features;
 pub(crate) mod globals;
+pub(crate) mod icrc21;
 
 #[cfg(feature = "ckbtc")]
 pub(crate) mod ckbtc;
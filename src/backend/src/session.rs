@@ -0,0 +1,182 @@
+use crate::types::{from_der, oid, ASN1Block, Ed25519Signature, VerifyingKey};
+use k256::ecdsa::signature::Verifier;
+
+/// Session public key algorithms accepted for proof of possession.
+enum Algorithm {
+    Ed25519,
+    P256,
+    Secp256k1,
+}
+
+/// Splits a DER `SubjectPublicKeyInfo` into its algorithm and raw public key.
+fn parse(der: &[u8]) -> Result<(Algorithm, Vec<u8>), String> {
+    let invalid = || "Session public key is invalid".to_owned();
+    let blocks = from_der(der).map_err(|e| format!("Session public key is invalid: {}", e))?;
+
+    let Some(ASN1Block::Sequence(_, info)) = blocks.first() else {
+        return Err(invalid());
+    };
+    let [ASN1Block::Sequence(_, algorithm), ASN1Block::BitString(_, _, key)] = info.as_slice()
+    else {
+        return Err(invalid());
+    };
+
+    let algorithm = match algorithm.as_slice() {
+        [ASN1Block::ObjectIdentifier(_, id)] if *id == oid!(1, 3, 101, 112) => Algorithm::Ed25519,
+        [ASN1Block::ObjectIdentifier(_, id), ASN1Block::ObjectIdentifier(_, curve)]
+            if *id == oid!(1, 2, 840, 10045, 2, 1) =>
+        {
+            if *curve == oid!(1, 2, 840, 10045, 3, 1, 7) {
+                Algorithm::P256
+            } else if *curve == oid!(1, 3, 132, 0, 10) {
+                Algorithm::Secp256k1
+            } else {
+                return Err("Session public key curve is not supported".to_owned());
+            }
+        }
+        _ => return Err("Session public key algorithm is not supported".to_owned()),
+    };
+
+    Ok((algorithm, key.clone()))
+}
+
+/// Verifies that `signature` over `message` was made with the private key of the DER-encoded
+/// session public key.
+///
+/// Ed25519 signatures are 64 bytes. ECDSA signatures on P-256 and secp256k1 are 64-byte `r || s`
+/// over the SHA-256 digest of `message`.
+pub(crate) fn verify(session: &[u8], message: &[u8], signature: &[u8]) -> Result<(), String> {
+    let invalid = || "Session signature is invalid".to_owned();
+    let mismatch = || "Session signature mismatch".to_owned();
+    let (algorithm, key) = parse(session)?;
+
+    match algorithm {
+        Algorithm::Ed25519 => {
+            let key = <[u8; 32]>::try_from(key.as_slice()).map_err(|_| invalid())?;
+            let key = VerifyingKey::from_bytes(&key).map_err(|_| invalid())?;
+            let signature = Ed25519Signature::from_slice(signature).map_err(|_| invalid())?;
+
+            key.verify_strict(message, &signature)
+                .map_err(|_| mismatch())
+        }
+        Algorithm::P256 => {
+            let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(&key).map_err(|_| invalid())?;
+            let signature = p256::ecdsa::Signature::from_slice(signature).map_err(|_| invalid())?;
+
+            key.verify(message, &signature).map_err(|_| mismatch())
+        }
+        Algorithm::Secp256k1 => {
+            let key = k256::ecdsa::VerifyingKey::from_sec1_bytes(&key).map_err(|_| invalid())?;
+            let signature = k256::ecdsa::Signature::from_slice(signature).map_err(|_| invalid())?;
+            let signature = signature.normalize_s().unwrap_or(signature);
+
+            key.verify(message, &signature).map_err(|_| mismatch())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::to_der;
+
+    const MESSAGE: &[u8] = b"prepared message hash";
+
+    /// DER `SubjectPublicKeyInfo` of a raw public key.
+    fn der(algorithm: Vec<ASN1Block>, key: &[u8]) -> Vec<u8> {
+        let info = ASN1Block::Sequence(
+            0,
+            vec![
+                ASN1Block::Sequence(0, algorithm),
+                ASN1Block::BitString(0, key.len() * 8, key.to_vec()),
+            ],
+        );
+
+        to_der(&info).unwrap()
+    }
+
+    fn ec(curve: simple_asn1::OID, key: &[u8]) -> Vec<u8> {
+        let algorithm = vec![
+            ASN1Block::ObjectIdentifier(0, oid!(1, 2, 840, 10045, 2, 1)),
+            ASN1Block::ObjectIdentifier(0, curve),
+        ];
+
+        der(algorithm, key)
+    }
+
+    #[test]
+    fn ed25519_session_signature() {
+        use ed25519_dalek::Signer;
+
+        let key = ed25519_dalek::SigningKey::from_bytes(&[1; 32]);
+        let session = der(
+            vec![ASN1Block::ObjectIdentifier(0, oid!(1, 3, 101, 112))],
+            key.verifying_key().as_bytes(),
+        );
+        let signature = key.sign(MESSAGE).to_bytes();
+
+        assert!(verify(&session, MESSAGE, &signature).is_ok());
+        assert_eq!(
+            verify(&session, b"other message", &signature),
+            Err("Session signature mismatch".to_owned())
+        );
+        assert_eq!(
+            verify(&session, MESSAGE, &signature[1..]),
+            Err("Session signature is invalid".to_owned())
+        );
+    }
+
+    #[test]
+    fn p256_session_signature() {
+        use p256::ecdsa::signature::Signer;
+
+        let key = p256::ecdsa::SigningKey::from_slice(&[1; 32]).unwrap();
+        let point = key.verifying_key().to_encoded_point(false);
+        let session = ec(oid!(1, 2, 840, 10045, 3, 1, 7), point.as_bytes());
+        let signature: p256::ecdsa::Signature = key.sign(MESSAGE);
+
+        assert!(verify(&session, MESSAGE, &signature.to_bytes()).is_ok());
+        assert_eq!(
+            verify(&session, b"other message", &signature.to_bytes()),
+            Err("Session signature mismatch".to_owned())
+        );
+    }
+
+    #[test]
+    fn secp256k1_session_signature() {
+        use k256::ecdsa::signature::Signer;
+
+        let key = k256::ecdsa::SigningKey::from_slice(&[1; 32]).unwrap();
+        let point = key.verifying_key().to_encoded_point(false);
+        let session = ec(oid!(1, 3, 132, 0, 10), point.as_bytes());
+        let signature: k256::ecdsa::Signature = key.sign(MESSAGE);
+
+        assert!(verify(&session, MESSAGE, &signature.to_bytes()).is_ok());
+        assert_eq!(
+            verify(&session, b"other message", &signature.to_bytes()),
+            Err("Session signature mismatch".to_owned())
+        );
+    }
+
+    #[test]
+    fn unsupported_session_keys() {
+        let p384 = ec(oid!(1, 3, 132, 0, 34), &[4; 97]);
+        let rsa = der(
+            vec![ASN1Block::ObjectIdentifier(
+                0,
+                oid!(1, 2, 840, 113549, 1, 1, 1),
+            )],
+            &[0; 32],
+        );
+
+        assert_eq!(
+            verify(&p384, MESSAGE, &[0; 64]),
+            Err("Session public key curve is not supported".to_owned())
+        );
+        assert_eq!(
+            verify(&rsa, MESSAGE, &[0; 64]),
+            Err("Session public key algorithm is not supported".to_owned())
+        );
+        assert!(verify(b"not der", MESSAGE, &[0; 64]).is_err());
+    }
+}
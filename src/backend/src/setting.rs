@@ -1,6 +1,10 @@
+use crate::accounts::validate_application;
+use crate::telegram::PUBLIC_KEY;
+use crate::template;
 use crate::types::{
-    caller_principal, canister_principal, states, utils, Bound, CandidType, Cow, Deserialize,
-    Memory, Principal, RefCell, Serialize, Set, StableCell, Storable,
+    caller_principal, canister_principal, states, utils, Application, Bot, Bound, ByteBuf,
+    CandidType, Cow, Deserialize, Map, Memory, Principal, RefCell, Serialize, Set, StableCell,
+    Storable, Target,
 };
 
 #[derive(CandidType, Clone, Deserialize, Serialize)]
@@ -8,33 +12,464 @@ pub struct Setting {
     expiration_minute: u64,
     authorities: Set<Principal>,
     canisters: Set<Principal>,
+    bots: Option<Map<u64, Bot>>,
+    public_keys: Option<Set<ByteBuf>>,
+    proof_expiration_minute: Option<u64>,
+    proof_not_before: Option<u64>,
+    session_proof: Option<bool>,
+    targets: Option<Map<Principal, Target>>,
+    min_expiration_minute: Option<u64>,
+    max_expiration_minute: Option<u64>,
+    message_expiration_minute: Option<u64>,
+    max_messages: Option<u64>,
+    max_user_messages: Option<u64>,
+    templates: Option<Map<String, String>>,
+    applications: Option<Map<String, Application>>,
+    signing_canister: Option<Principal>,
+    successor: Option<Principal>,
+    self_erasure: Option<bool>,
 }
 
+/// Default maximum age of a Telegram proof's `auth_date`, in minutes.
+const PROOF_EXPIRATION_MINUTE: u64 = 60;
+
+/// Default time a prepared message waits for `login`, in minutes.
+const MESSAGE_EXPIRATION_MINUTE: u64 = 10;
+
+/// Default maximum number of pending prepared messages, overall and per user.
+const MAX_MESSAGES: u64 = 100_000;
+const MAX_USER_MESSAGES: u64 = 10;
+
 impl Setting {
     pub(crate) fn init<M: Memory>(memory: M) -> RefCell<StableCell<Self, M>> {
         RefCell::new(StableCell::init(memory, Self::default()).unwrap())
     }
 
-    pub fn expiration(&self) -> u64 {
-        utils::now_add_minute(self.expiration_minute)
-    }
+    /// Delegation lifetime in minutes for the given bot and targets.
+    ///
+    /// A requested lifetime is clamped to the configured bounds, where the upper bound defaults to
+    /// the bot's or global expiration; otherwise that expiration applies. The result is then
+    /// capped by the allowlist policy of each target.
+    pub fn expiration_minute_of<'a>(
+        &self,
+        bot: u64,
+        targets: impl IntoIterator<Item = &'a Principal>,
+        requested: Option<u64>,
+    ) -> u64 {
+        let default = self
+            .bot(bot)
+            .ok()
+            .and_then(Bot::expiration_minute)
+            .unwrap_or(self.expiration_minute);
+        let minute = match requested {
+            Some(requested) => {
+                let min = self.min_expiration_minute.unwrap_or(1);
+                let max = self.max_expiration_minute.unwrap_or(default);
+
+                requested.max(min).min(max)
+            }
+            None => default,
+        };
 
-    #[allow(unused)]
-    pub fn expiration_minute(&self) -> u64 {
-        self.expiration_minute
+        targets
+            .into_iter()
+            .filter_map(|target| self.target(target))
+            .filter_map(Target::expiration_minute)
+            .fold(minute, u64::min)
     }
 
     pub(crate) fn set_expiration_minute(&mut self, expiration_minute: u64) {
         self.expiration_minute = expiration_minute;
     }
 
-    pub(crate) fn authorized(&self, principal: &Principal) -> bool {
-        self.authorities.contains(principal)
+    pub(crate) fn set_expiration_bounds(
+        &mut self,
+        min: Option<u64>,
+        max: Option<u64>,
+    ) -> Result<(), String> {
+        if let (Some(min), Some(max)) = (min, max) {
+            if min > max {
+                return Err(format!(
+                    "Minimum expiration {min} is greater than maximum expiration {max}"
+                ));
+            }
+        }
+
+        self.min_expiration_minute = min;
+        self.max_expiration_minute = max;
+
+        Ok(())
+    }
+
+    pub(crate) fn bot(&self, id: u64) -> Result<&Bot, String> {
+        self.bots
+            .as_ref()
+            .and_then(|bots| bots.get(&id))
+            .ok_or(format!("Telegram bot {id} is not configured"))
+    }
+
+    pub(crate) fn bots(&self) -> impl Iterator<Item = (&u64, &Bot)> {
+        self.bots.iter().flatten()
+    }
+
+    /// Bot the principals of a bot's users are isolated to: the bot itself, if it is isolated.
+    pub(crate) fn isolated_bot(&self, id: u64) -> Option<u64> {
+        self.bot(id).is_ok_and(Bot::isolated).then_some(id)
+    }
+
+    /// Canister whose id is embedded in the public keys of user principals: this canister,
+    /// unless it replaced another SIWT canister whose principals it keeps serving.
+    pub(crate) fn signing_canister(&self) -> Principal {
+        self.signing_canister.unwrap_or_else(canister_principal)
+    }
+
+    /// Whether user principals belong to another canister, which bridges to this one.
+    pub(crate) fn migrated(&self) -> bool {
+        self.signing_canister() != canister_principal()
+    }
+
+    pub(crate) fn set_signing_canister(&mut self, canister: Option<Principal>) {
+        self.signing_canister = canister;
     }
 
-    #[allow(unused)]
-    pub(crate) fn authorities(&self) -> impl Iterator<Item = &Principal> {
-        self.authorities.iter()
+    /// Canister that replaced this one and may request bridge delegations.
+    pub(crate) fn successor(&self) -> Option<Principal> {
+        self.successor
+    }
+
+    pub(crate) fn set_successor(&mut self, canister: Option<Principal>) {
+        self.successor = canister;
+    }
+
+    /// Whether users may erase their own data with `forget`.
+    pub(crate) fn self_erasure(&self) -> bool {
+        self.self_erasure.unwrap_or(false)
+    }
+
+    pub(crate) fn set_self_erasure(&mut self, self_erasure: bool) {
+        self.self_erasure = Some(self_erasure);
+    }
+
+    /// Whether at least one bot allows public login.
+    pub(crate) fn public(&self) -> bool {
+        self.bots().any(|(_, bot)| bot.public())
+    }
+
+    /// Whether the principal may log in with proofs of the given bot.
+    pub(crate) fn permits(&self, principal: &Principal, bot: u64) -> bool {
+        self.authorized(principal) || self.bot(bot).is_ok_and(Bot::public)
+    }
+
+    pub(crate) fn set_bot(&mut self, id: u64, bot: Bot) -> Result<(), String> {
+        bot.validate(id)?;
+        self.bots.get_or_insert_with(Map::new).insert(id, bot);
+
+        Ok(())
+    }
+
+    pub(crate) fn remove_bot(&mut self, id: u64) -> Option<Bot> {
+        self.bots.as_mut()?.remove(&id)
+    }
+
+    /// Whether requested targets are checked against an allowlist.
+    pub(crate) fn target_allowlist(&self) -> bool {
+        self.targets.is_some()
+    }
+
+    pub(crate) fn target(&self, canister: &Principal) -> Option<&Target> {
+        self.targets.as_ref()?.get(canister)
+    }
+
+    /// Friendly name of a canister for consent messages, its principal text otherwise.
+    pub(crate) fn canister_name(&self, canister: &Principal) -> String {
+        match self.target(canister).and_then(Target::name) {
+            Some(name) => format!("{name} ({})", canister.to_text()),
+            None => canister.to_text(),
+        }
+    }
+
+    /// Consent message template for a language code such as Telegram's `language_code`, along
+    /// with the language of the template found.
+    pub(crate) fn template(&self, language: Option<&str>) -> (&str, &str) {
+        template::select(self.templates.as_ref(), language)
+    }
+
+    pub(crate) fn set_template(
+        &mut self,
+        language: &str,
+        template: Option<String>,
+    ) -> Result<(), String> {
+        let language = language.trim().to_lowercase();
+
+        if language.is_empty() {
+            return Err("Template language is empty".to_owned());
+        }
+
+        match template {
+            Some(template) => {
+                template::validate(&template)?;
+                self.templates
+                    .get_or_insert_with(Map::new)
+                    .insert(language, template);
+            }
+            None => {
+                if let Some(templates) = self.templates.as_mut() {
+                    templates.remove(&language);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Allowlisted targets that are added to every delegation.
+    pub(crate) fn default_targets(&self) -> impl Iterator<Item = &Principal> {
+        self.targets
+            .iter()
+            .flatten()
+            .filter(|(_, target)| target.default())
+            .map(|(canister, _)| canister)
+    }
+
+    /// Every target of a delegation issued with a bot: the setting canisters, the default
+    /// allowlist targets, the bot's default canisters and the requested canisters.
+    pub(crate) fn targets_of(
+        &self,
+        bot: u64,
+        canisters: impl IntoIterator<Item = Principal>,
+    ) -> Set<Principal> {
+        let defaults = self.bot(bot).into_iter().flat_map(Bot::canisters);
+
+        self.canisters()
+            .chain(self.default_targets())
+            .chain(defaults)
+            .copied()
+            .chain(canisters)
+            .collect()
+    }
+
+    /// Rejects requested targets outside the allowlist, once one is configured. This canister,
+    /// the setting canisters and the bot's default canisters are always allowed.
+    pub(crate) fn check_targets(
+        &self,
+        bot: u64,
+        canisters: impl IntoIterator<Item = Principal>,
+    ) -> Result<(), String> {
+        let Some(targets) = &self.targets else {
+            return Ok(());
+        };
+        let trusted = |canister: &Principal| {
+            *canister == canister_principal()
+                || self.canisters.contains(canister)
+                || self
+                    .bot(bot)
+                    .is_ok_and(|bot| bot.canisters().any(|c| c == canister))
+        };
+
+        for canister in canisters {
+            if !targets.contains_key(&canister) && !trusted(&canister) {
+                return Err(format!(
+                    "Canister {} is not an allowed delegation target",
+                    canister.to_text()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn set_target(&mut self, canister: Principal, target: Target) {
+        self.targets
+            .get_or_insert_with(Map::new)
+            .insert(canister, target);
+    }
+
+    pub(crate) fn remove_target(&mut self, canister: &Principal) -> Option<Target> {
+        self.targets.as_mut()?.remove(canister)
+    }
+
+    fn applications(&self) -> impl Iterator<Item = (&String, &Application)> {
+        self.applications.iter().flatten()
+    }
+
+    /// Application an origin belongs to.
+    fn origin_application(&self, origin: &str) -> Option<(&String, &Application)> {
+        self.applications()
+            .find(|(_, application)| application.origins().any(|o| o == origin))
+    }
+
+    /// Application a canister belongs to.
+    fn canister_application(&self, canister: &Principal) -> Option<&String> {
+        self.applications()
+            .find(|(_, application)| application.canisters().any(|c| c == canister))
+            .map(|(id, _)| id)
+    }
+
+    /// Id of the application an origin belongs to, once the bot and the requested canisters
+    /// are checked against it. Without an origin, the delegation is for the user's principal
+    /// shared by every application, and none of its targets, including the default ones, may
+    /// be a canister of an application.
+    pub(crate) fn application_of(
+        &self,
+        origin: Option<&str>,
+        bot: u64,
+        canisters: impl IntoIterator<Item = Principal>,
+    ) -> Result<Option<&str>, String> {
+        let Some(origin) = origin else {
+            for canister in self.targets_of(bot, canisters) {
+                if let Some(id) = self.canister_application(&canister) {
+                    return Err(format!(
+                        "Canister {} belongs to application {id}",
+                        canister.to_text()
+                    ));
+                }
+            }
+
+            return Ok(None);
+        };
+
+        let (id, application) = self
+            .origin_application(origin)
+            .ok_or(format!("Origin {origin} is not registered"))?;
+
+        if !application.bots().any(|b| *b == bot) {
+            return Err(format!(
+                "Telegram bot {bot} does not serve application {id}"
+            ));
+        }
+
+        for canister in canisters {
+            if canister != canister_principal() && !application.canisters().any(|c| *c == canister)
+            {
+                return Err(format!(
+                    "Canister {} is not a target of application {id}",
+                    canister.to_text()
+                ));
+            }
+        }
+
+        Ok(Some(id))
+    }
+
+    /// Registers or replaces an application. An origin or a canister can belong to one
+    /// application only.
+    pub(crate) fn set_application(
+        &mut self,
+        id: &str,
+        application: Application,
+    ) -> Result<(), String> {
+        validate_application(id)?;
+        application.validate()?;
+
+        for origin in application.origins() {
+            if let Some((other, _)) = self.origin_application(origin) {
+                if other != id {
+                    return Err(format!("Origin {origin} belongs to application {other}"));
+                }
+            }
+        }
+
+        for canister in application.canisters() {
+            if let Some(other) = self.canister_application(canister) {
+                if other != id {
+                    return Err(format!(
+                        "Canister {} belongs to application {other}",
+                        canister.to_text()
+                    ));
+                }
+            }
+        }
+
+        self.applications
+            .get_or_insert_with(Map::new)
+            .insert(id.to_owned(), application);
+
+        Ok(())
+    }
+
+    pub(crate) fn remove_application(&mut self, id: &str) -> Option<Application> {
+        self.applications.as_mut()?.remove(id)
+    }
+
+    /// Telegram public keys accepted for `signature` verification, the production key by default.
+    pub(crate) fn public_keys(&self) -> Vec<&[u8]> {
+        match &self.public_keys {
+            Some(keys) if !keys.is_empty() => keys.iter().map(|key| key.as_slice()).collect(),
+            _ => vec![&PUBLIC_KEY[..]],
+        }
+    }
+
+    pub(crate) fn set_public_keys(&mut self, public_keys: Option<Set<ByteBuf>>) {
+        self.public_keys = public_keys;
+    }
+
+    /// Maximum age of a Telegram proof's `auth_date` accepted by `prepare`, in minutes.
+    pub(crate) fn proof_expiration_minute(&self) -> u64 {
+        self.proof_expiration_minute
+            .unwrap_or(PROOF_EXPIRATION_MINUTE)
+    }
+
+    /// Sets the proof window. Consumed proofs are forgotten once they leave the window, so every
+    /// proof that has left the current one stays expired when the window is raised.
+    pub(crate) fn set_proof_expiration_minute(&mut self, proof_expiration_minute: u64) {
+        let expired =
+            utils::now_second().saturating_sub(self.proof_expiration_minute().saturating_mul(60));
+
+        self.proof_not_before = Some(expired.max(self.proof_not_before()));
+        self.proof_expiration_minute = Some(proof_expiration_minute);
+    }
+
+    /// Earliest `auth_date` accepted by `prepare`, in seconds, whatever the proof window.
+    pub(crate) fn proof_not_before(&self) -> u64 {
+        self.proof_not_before.unwrap_or_default()
+    }
+
+    /// Time a prepared message waits for `login`, in minutes.
+    pub(crate) fn message_expiration_minute(&self) -> u64 {
+        self.message_expiration_minute
+            .unwrap_or(MESSAGE_EXPIRATION_MINUTE)
+    }
+
+    pub(crate) fn set_message_expiration_minute(&mut self, message_expiration_minute: u64) {
+        self.message_expiration_minute = Some(message_expiration_minute);
+    }
+
+    /// Maximum number of pending prepared messages before the oldest are evicted.
+    pub(crate) fn max_messages(&self) -> u64 {
+        self.max_messages.unwrap_or(MAX_MESSAGES)
+    }
+
+    /// Maximum number of pending prepared messages per user before their oldest are evicted.
+    pub(crate) fn max_user_messages(&self) -> u64 {
+        self.max_user_messages.unwrap_or(MAX_USER_MESSAGES)
+    }
+
+    pub(crate) fn set_message_limits(
+        &mut self,
+        max: Option<u64>,
+        max_user: Option<u64>,
+    ) -> Result<(), String> {
+        if max == Some(0) || max_user == Some(0) {
+            return Err("Pending message limits must be positive".to_owned());
+        }
+
+        self.max_messages = max;
+        self.max_user_messages = max_user;
+
+        Ok(())
+    }
+
+    /// Whether `login` requires a signature over the prepared hash made with the session key.
+    pub(crate) fn session_proof(&self) -> bool {
+        self.session_proof.unwrap_or(false)
+    }
+
+    pub(crate) fn set_session_proof(&mut self, session_proof: bool) {
+        self.session_proof = Some(session_proof);
+    }
+
+    pub(crate) fn authorized(&self, principal: &Principal) -> bool {
+        self.authorities.contains(principal)
     }
 
     pub(crate) fn canisters(&self) -> impl Iterator<Item = &Principal> {
@@ -52,13 +487,84 @@ impl Setting {
         self.canisters.insert(canister_principal());
     }
 
+    /// Merges the given setting into the stored one, trapping before anything is stored if any
+    /// part of it is invalid.
     pub(crate) fn merge(self) {
-        let mut setting = states::setting::get();
+        match self.merge_into(states::setting::get()) {
+            Ok(setting) => states::setting::set(setting),
+            Err(e) => ic_cdk::trap(&format!("Invalid setting: {e}")),
+        }
+    }
 
+    fn merge_into(self, mut setting: Setting) -> Result<Setting, String> {
         setting.set_expiration_minute(self.expiration_minute);
         setting.extends(self.authorities, self.canisters);
 
-        states::setting::set(setting);
+        for (id, bot) in self.bots.into_iter().flatten() {
+            setting.set_bot(id, bot)?;
+        }
+
+        if self.public_keys.is_some() {
+            setting.set_public_keys(self.public_keys);
+        }
+
+        if let Some(minute) = self.proof_expiration_minute {
+            setting.set_proof_expiration_minute(minute);
+        }
+
+        if let Some(session_proof) = self.session_proof {
+            setting.set_session_proof(session_proof);
+        }
+
+        for (canister, target) in self.targets.into_iter().flatten() {
+            setting.set_target(canister, target);
+        }
+
+        if let Some(minute) = self.message_expiration_minute {
+            setting.set_message_expiration_minute(minute);
+        }
+
+        if self.max_messages.is_some() || self.max_user_messages.is_some() {
+            setting.set_message_limits(self.max_messages, self.max_user_messages)?;
+        }
+
+        if self.signing_canister.is_some() {
+            setting.set_signing_canister(self.signing_canister);
+        }
+
+        if self.successor.is_some() {
+            setting.set_successor(self.successor);
+        }
+
+        if let Some(self_erasure) = self.self_erasure {
+            setting.set_self_erasure(self_erasure);
+        }
+
+        for (id, application) in self.applications.into_iter().flatten() {
+            setting.set_application(&id, application)?;
+        }
+
+        for (language, template) in self.templates.into_iter().flatten() {
+            setting.set_template(&language, Some(template))?;
+        }
+
+        if self.min_expiration_minute.is_some() || self.max_expiration_minute.is_some() {
+            setting
+                .set_expiration_bounds(self.min_expiration_minute, self.max_expiration_minute)?;
+        }
+
+        Ok(setting)
+    }
+
+    /// Copy of the setting that is safe to return to callers, with secrets removed.
+    pub(crate) fn redacted(mut self) -> Self {
+        if let Some(bots) = self.bots.take() {
+            let bots = bots.into_iter().map(|(id, bot)| (id, bot.redacted()));
+
+            self.bots = Some(bots.collect());
+        }
+
+        self
     }
 
     pub(crate) fn get() -> Self {
@@ -76,6 +582,22 @@ impl Default for Setting {
             expiration_minute: 120,
             authorities: [caller_principal()].into(),
             canisters: [canister_principal()].into(),
+            bots: None,
+            public_keys: None,
+            proof_expiration_minute: None,
+            proof_not_before: None,
+            session_proof: None,
+            targets: None,
+            min_expiration_minute: None,
+            max_expiration_minute: None,
+            message_expiration_minute: None,
+            max_messages: None,
+            max_user_messages: None,
+            templates: None,
+            applications: None,
+            signing_canister: None,
+            successor: None,
+            self_erasure: None,
         }
     }
 }
@@ -91,3 +613,65 @@ impl Storable for Setting {
         serde_cbor::from_slice(&bytes).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canister(id: u8) -> Principal {
+        Principal::from_slice(&[id; 10])
+    }
+
+    /// Application `dex` with one origin, served by bot 1 and targeting canister 1. Bot 1 also
+    /// targets canister 1 by default; bot 2 has no default canisters.
+    fn setting() -> Setting {
+        serde_json::from_value(serde_json::json!({
+            "expiration_minute": 120,
+            "authorities": [],
+            "canisters": [],
+            "bots": {
+                "1": { "verification": "Signature", "canisters": [canister(1).to_text()] },
+                "2": { "verification": "Signature", "canisters": [] },
+            },
+            "applications": {
+                "dex": {
+                    "origins": ["https://dex.example"],
+                    "bots": [1],
+                    "canisters": [canister(1).to_text()],
+                },
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn origins_resolve_to_their_application() {
+        let setting = setting();
+
+        assert_eq!(
+            setting.application_of(Some("https://dex.example"), 1, []),
+            Ok(Some("dex"))
+        );
+        assert_eq!(
+            setting.application_of(Some("https://dex.example"), 2, []),
+            Err("Telegram bot 2 does not serve application dex".to_owned())
+        );
+        assert_eq!(
+            setting.application_of(Some("https://other.example"), 1, []),
+            Err("Origin https://other.example is not registered".to_owned())
+        );
+    }
+
+    #[test]
+    fn sign_in_without_origin_cannot_target_applications() {
+        let setting = setting();
+        let belongs = Err(format!(
+            "Canister {} belongs to application dex",
+            canister(1).to_text()
+        ));
+
+        assert_eq!(setting.application_of(None, 2, [canister(2)]), Ok(None));
+        assert_eq!(setting.application_of(None, 2, [canister(1)]), belongs);
+        assert_eq!(setting.application_of(None, 1, []), belongs);
+    }
+}
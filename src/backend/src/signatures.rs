@@ -31,19 +31,138 @@ impl PartialOrd for SigExpiration {
     }
 }
 
-#[derive(Default)]
+/// Metadata of an issued delegation, kept in stable memory next to its signature.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Signed {
+    session: ByteBuf,
+    expiration: u64,
+    #[serde(default)]
+    revoked: bool,
+    #[serde(default)]
+    targets: Set<Principal>,
+    #[serde(default)]
+    created: u64,
+    #[serde(default)]
+    label: Option<String>,
+}
+
+impl Signed {
+    pub fn new(
+        session: &[u8],
+        targets: impl Iterator<Item = Principal>,
+        expiration: u64,
+        label: Option<&str>,
+    ) -> Self {
+        Self {
+            session: ByteBuf::from(session),
+            expiration,
+            revoked: false,
+            targets: targets.collect(),
+            created: utils::now(),
+            label: label.map(str::to_owned),
+        }
+    }
+
+    pub fn session(&self) -> &[u8] {
+        &self.session
+    }
+
+    pub fn expiration(&self) -> u64 {
+        self.expiration
+    }
+
+    pub fn targets(&self) -> impl Iterator<Item = &Principal> {
+        self.targets.iter()
+    }
+
+    /// Time the delegation was stored, zero for delegations stored before it was recorded.
+    pub fn created(&self) -> u64 {
+        self.created
+    }
+
+    /// Device label given in `prepare`.
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    pub fn revoked(&self) -> bool {
+        self.revoked
+    }
+}
+
+impl Storable for Signed {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        serde_cbor::to_vec(self).unwrap().into()
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        serde_cbor::from_slice(&bytes).unwrap()
+    }
+}
+
+/// Certified delegation signatures.
+///
+/// The certified tree and the expiration heap live on the heap; every entry is mirrored in
+/// stable memory so both can be rebuilt by `restore` after an upgrade. Revoked entries leave
+/// the certified tree but stay in stable memory until they expire, so they can be reported.
+///
+/// Entries are indexed by delegation hash and by the hash of their session key, so they can be
+/// revoked by either without scanning every entry.
 pub struct Signatures {
     certified: RbTree<Hash, RbTree<Hash, Unit>>,
     expiration: BinaryHeap<SigExpiration>,
+    entries: StableBTreeMap<(Hash, Hash), Signed, VM>,
+    hashes: StableBTreeMap<(Hash, Hash), (), VM>,
+    session_keys: StableBTreeMap<(Hash, Hash, Hash), (), VM>,
 }
 
 impl Signatures {
-    pub fn put(&mut self, seed: Hash, hash: Hash) -> Hash {
-        self.prune();
+    pub(crate) fn new(memories: [VM; 3]) -> Self {
+        let [m1, m2, m3] = memories;
 
-        let setting = Setting::get();
-        let expiration = setting.expiration();
+        Self {
+            certified: RbTree::new(),
+            expiration: BinaryHeap::new(),
+            entries: StableBTreeMap::init(m1),
+            hashes: StableBTreeMap::init(m2),
+            session_keys: StableBTreeMap::init(m3),
+        }
+    }
 
+    /// Rebuilds the certified tree and the expiration heap from stable memory, dropping
+    /// entries that expired meanwhile. Indexes missing entries stored before they existed.
+    pub fn restore(&mut self) -> usize {
+        let now = utils::now();
+        let (live, expired): (Vec<_>, Vec<_>) = self
+            .entries
+            .iter()
+            .partition(|(_, signed)| signed.expiration > now);
+
+        for ((seed, hash), signed) in &expired {
+            self.entries.remove(&(*seed, *hash));
+            self.unindex(*seed, *hash, signed);
+        }
+
+        for ((seed, hash), signed) in live {
+            self.index(seed, hash, &signed);
+
+            if !signed.revoked {
+                self.certify(seed, hash);
+            }
+
+            self.expiration.push(SigExpiration {
+                seed,
+                hash,
+                expiration: signed.expiration,
+            });
+        }
+
+        self.expiration.len()
+    }
+
+    fn certify(&mut self, seed: Hash, hash: Hash) {
         if self.certified.get(&seed[..]).is_none() {
             let mut map = RbTree::new();
 
@@ -55,6 +174,41 @@ impl Signatures {
                 map.insert(hash, Unit);
             });
         }
+    }
+
+    fn uncertify(&mut self, seed: Hash, hash: Hash) {
+        let mut is_empty = false;
+
+        self.certified.modify(&seed[..], |m| {
+            m.delete(&hash[..]);
+            is_empty = m.is_empty();
+        });
+
+        if is_empty {
+            self.certified.delete(&seed[..]);
+        }
+    }
+
+    fn index(&mut self, seed: Hash, hash: Hash, signed: &Signed) {
+        self.hashes.insert((hash, seed), ());
+        self.session_keys
+            .insert((hash::bytes(signed.session()), seed, hash), ());
+    }
+
+    fn unindex(&mut self, seed: Hash, hash: Hash, signed: &Signed) {
+        self.hashes.remove(&(hash, seed));
+        self.session_keys
+            .remove(&(hash::bytes(signed.session()), seed, hash));
+    }
+
+    pub fn put(&mut self, seed: Hash, hash: Hash, signed: Signed) -> Hash {
+        self.prune();
+
+        let expiration = signed.expiration;
+
+        self.index(seed, hash, &signed);
+        self.entries.insert((seed, hash), signed);
+        self.certify(seed, hash);
         self.expiration.push(SigExpiration {
             seed,
             hash,
@@ -64,49 +218,112 @@ impl Signatures {
         hash
     }
 
-    pub fn puts<const N: usize>(&mut self, seed: Hash, hashes: [Hash; N]) -> [Hash; N] {
-        self.prune();
+    pub fn get(&self, seed: Hash, hash: Hash) -> Option<Signed> {
+        self.entries.get(&(seed, hash))
+    }
 
-        let setting = Setting::get();
-        let expiration = setting.expiration();
+    /// Unexpired, unrevoked delegations issued for the given seed hash.
+    pub fn sessions(&self, seed: Hash) -> Vec<(Hash, Signed)> {
+        let now = utils::now();
 
-        if self.certified.get(&seed[..]).is_none() {
-            self.certified
-                .insert(seed, RbTree::from_iter(hashes.map(|hash| (hash, Unit))));
-        } else {
-            self.certified.modify(&seed[..], |map| {
-                for hash in hashes {
-                    map.insert(hash, Unit);
-                }
-            });
+        self.entries
+            .range((seed, [0; 32])..=(seed, [u8::MAX; 32]))
+            .filter(|(_, signed)| !signed.revoked && signed.expiration > now)
+            .map(|((_, hash), signed)| (hash, signed))
+            .collect()
+    }
+
+    pub fn delete(&mut self, seed: Hash, delegation: Hash) {
+        if let Some(signed) = self.entries.remove(&(seed, delegation)) {
+            self.unindex(seed, delegation, &signed);
         }
 
-        self.expiration.extend(hashes.map(|hash| SigExpiration {
-            seed,
-            hash,
-            expiration,
-        }));
+        self.uncertify(seed, delegation);
+    }
 
-        hashes
+    /// Removes a delegation from the certified tree and marks it revoked until it expires.
+    pub fn revoke(&mut self, seed: Hash, hash: Hash) -> bool {
+        let Some(mut signed) = self.get(seed, hash).filter(|signed| !signed.revoked) else {
+            return false;
+        };
+
+        signed.revoked = true;
+
+        self.entries.insert((seed, hash), signed);
+        self.uncertify(seed, hash);
+
+        true
     }
 
-    pub fn delete(&mut self, seed: Hash, delegation: Hash) {
-        let mut is_empty = false;
+    fn revokes(&mut self, keys: Vec<(Hash, Hash)>) -> usize {
+        keys.into_iter()
+            .filter(|(seed, hash)| self.revoke(*seed, *hash))
+            .count()
+    }
 
-        self.certified.modify(&seed[..], |m| {
-            m.delete(&delegation[..]);
-            is_empty = m.is_empty();
-        });
+    /// Revokes every delegation with the given delegation hash, as returned by `login`.
+    pub fn revoke_hash(&mut self, hash: Hash) -> usize {
+        let keys = self
+            .hashes
+            .range((hash, [0; 32])..=(hash, [u8::MAX; 32]))
+            .map(|((hash, seed), _)| (seed, hash))
+            .collect();
 
-        if is_empty {
-            self.certified.delete(&seed[..]);
+        self.revokes(keys)
+    }
+
+    /// Revokes every delegation issued to the given session public key.
+    pub fn revoke_session(&mut self, session: &[u8]) -> usize {
+        let session = hash::bytes(session);
+        let keys = self
+            .session_keys
+            .range((session, [0; 32], [0; 32])..=(session, [u8::MAX; 32], [u8::MAX; 32]))
+            .map(|((_, seed, hash), _)| (seed, hash))
+            .collect();
+
+        self.revokes(keys)
+    }
+
+    /// Revokes every delegation issued for the given seed hash, that is for one user.
+    pub fn revoke_seed(&mut self, seed: Hash) -> usize {
+        let keys = self
+            .entries
+            .range((seed, [0; 32])..=(seed, [u8::MAX; 32]))
+            .map(|(key, _)| key)
+            .collect();
+
+        self.revokes(keys)
+    }
+
+    /// Deletes every delegation issued for the given seed hash, revoked or not, with its
+    /// session key and label.
+    pub fn delete_seed(&mut self, seed: Hash) -> usize {
+        let keys = self
+            .entries
+            .range((seed, [0; 32])..=(seed, [u8::MAX; 32]))
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
+
+        for (seed, hash) in &keys {
+            self.delete(*seed, *hash);
         }
+
+        keys.len()
     }
 
     pub fn prune(&mut self) -> usize {
+        self.prune_up_to(10)
+    }
+
+    /// Removes every expired delegation, including revoked ones.
+    pub fn prune_all(&mut self) -> usize {
+        self.prune_up_to(usize::MAX)
+    }
+
+    fn prune_up_to(&mut self, limit: usize) -> usize {
         let now = utils::now();
         let mut pruned = 0;
-        let pruning = std::cmp::min(10, self.expiration.len());
+        let pruning = std::cmp::min(limit, self.expiration.len());
 
         for _ in 0..pruning {
             if let Some(expiration) = self.expiration.peek() {
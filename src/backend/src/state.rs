@@ -1,6 +1,6 @@
 use crate::types::{
     fork_hash, labeled_hash, set_certified_data, states, AsHashTree, Hash, RbTree, RefCell,
-    Signatures,
+    Signatures, VM,
 };
 
 pub const LABEL_ASSETS: &[u8] = b"http_assets";
@@ -14,6 +14,13 @@ pub struct State {
 }
 
 impl State {
+    pub(crate) fn init(memories: [VM; 3]) -> Self {
+        Self {
+            signatures: RefCell::new(Signatures::new(memories)),
+            assets: RefCell::new(AssetHashes::default()),
+        }
+    }
+
     pub fn with<F: FnOnce(&Self) -> R, R>(f: F) -> R {
         states::state(f)
     }
@@ -37,13 +44,13 @@ impl State {
 
         set_certified_data(&prefixed_root_hash[..]);
     }
-}
 
-impl Default for State {
-    fn default() -> Self {
-        Self {
-            signatures: RefCell::new(Signatures::default()),
-            assets: RefCell::new(AssetHashes::default()),
-        }
+    /// Rebuilds the certified signatures from stable memory and certifies the root hash again.
+    pub fn restore(&self) {
+        let signatures = &mut *self.signatures.borrow_mut();
+
+        signatures.restore();
+
+        self.update_root_hash(signatures);
     }
 }
@@ -5,15 +5,36 @@ thread_local! {
     static MEMORY_MANAGER: MemoryManagerType = RefCell::new(
         MemoryManager::init(DefaultMemoryImpl::default())
     );
-    static STATE: State = State::default();
-    static MESSAGES: RefCell<Messages> = RefCell::new(Messages::new());
+    static STATE: State = State::init([allocate(9), allocate(21), allocate(22)]);
+    static MESSAGES: RefCell<Messages> = Messages::init([
+        allocate(7),
+        allocate(8),
+        allocate(10),
+        allocate(11),
+    ]);
     static SETTING: SettingStateType = Setting::init(allocate(0));
     static ACCOUNTS: AccountsStateType = Accounts::init([
         allocate(1),
         allocate(2),
         allocate(3),
+        allocate(12),
+        allocate(13),
+        allocate(14),
+        allocate(15),
+        allocate(16),
+        allocate(17),
+        allocate(18),
+        allocate(19),
+        allocate(20),
+        allocate(23),
+        allocate(24),
     ]);
     static GLOBALS: RefCell<Globals> = Globals::init(allocate(4));
+    static PROOFS: RefCell<Proofs> = Proofs::init([
+        allocate(5),
+        allocate(6),
+    ]);
+    static COLLECTION: RefCell<Collection> = RefCell::default();
 }
 
 fn allocate(id: u8) -> VM {
@@ -52,6 +73,18 @@ pub(crate) mod accounts {
     }
 }
 
+pub(crate) mod collection {
+    use super::*;
+
+    pub fn get() -> Collection {
+        COLLECTION.with_borrow(Clone::clone)
+    }
+
+    pub fn borrow_mut<F: FnOnce(&mut Collection) -> R, R>(f: F) -> R {
+        COLLECTION.with_borrow_mut(f)
+    }
+}
+
 pub(crate) mod messages {
     use super::*;
 
@@ -75,3 +108,11 @@ pub(crate) mod globals {
         GLOBALS.with_borrow_mut(f)
     }
 }
+
+pub(crate) mod proofs {
+    use super::*;
+
+    pub fn borrow_mut<F: FnOnce(&mut Proofs) -> R, R>(f: F) -> R {
+        PROOFS.with_borrow_mut(f)
+    }
+}
@@ -0,0 +1,28 @@
+use crate::types::{CandidType, Deserialize, Serialize};
+
+/// Policy of one delegation target canister, keyed by principal in the settings allowlist.
+#[derive(CandidType, Clone, Deserialize, Serialize)]
+pub struct Target {
+    expiration_minute: Option<u64>,
+    #[serde(default)]
+    default: bool,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+impl Target {
+    /// Longest expiration of a delegation that includes this target, when capped.
+    pub fn expiration_minute(&self) -> Option<u64> {
+        self.expiration_minute
+    }
+
+    /// Whether the target is added to every delegation, even when not requested.
+    pub fn default(&self) -> bool {
+        self.default
+    }
+
+    /// Friendly name shown in consent messages instead of the principal.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
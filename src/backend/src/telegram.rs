@@ -0,0 +1,466 @@
+use crate::types::{
+    hash, CandidType, Deserialize, Digest, Ed25519Signature, Hash, HashMap, Hmac, Mac, Map,
+    Serialize, Sha256, VerifyingKey,
+};
+use base64::alphabet::URL_SAFE;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Base64url as used by Telegram, with or without padding.
+const BASE64_URL: GeneralPurpose = GeneralPurpose::new(
+    &URL_SAFE,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Telegram's Ed25519 public key for third-party validation in production.
+pub const PUBLIC_KEY: [u8; 32] =
+    hex_literal::hex!("e7bf03a2fa4602af4580703d88dda5bb59f32ed8b02a56c187fe7d34caed242d");
+
+/// How `initData` is verified.
+#[derive(CandidType, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum Verification {
+    /// `hash` field, HMAC-SHA256 keyed with the bot token.
+    #[default]
+    Token,
+    /// `signature` field, Ed25519 signed by Telegram; only the bot id is needed.
+    Signature,
+}
+
+/// Telegram login proof accepted by `prepare`.
+#[derive(CandidType, Clone, Deserialize)]
+pub enum Proof {
+    /// Raw Mini App `initData` query string.
+    InitData(String),
+    /// Login Widget callback fields.
+    Widget(Widget),
+}
+
+impl Proof {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Proof::InitData(init_data) if init_data.trim().is_empty() => {
+                Err("Init data is empty".to_owned())
+            }
+            Proof::Widget(widget) if widget.hash.trim().is_empty() => {
+                Err("Widget hash is empty".to_owned())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Telegram proof that passed verification.
+pub struct Verified {
+    user: User,
+    auth_date: u64,
+    hash: Hash,
+}
+
+impl Verified {
+    pub fn new(user: User, auth_date: u64, hash: Hash) -> Self {
+        Self {
+            user,
+            auth_date,
+            hash,
+        }
+    }
+
+    pub fn user(&self) -> &User {
+        &self.user
+    }
+
+    /// Unix time in seconds at which Telegram issued the proof.
+    pub fn auth_date(&self) -> u64 {
+        self.auth_date
+    }
+
+    /// Digest identifying the proof, used to reject replays.
+    pub fn hash(&self) -> Hash {
+        self.hash
+    }
+}
+
+/// Telegram user as embedded in the `user` field of Mini App `initData`.
+#[derive(Clone, Deserialize)]
+pub struct User {
+    id: u64,
+    #[serde(default)]
+    username: Option<String>,
+}
+
+impl User {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+}
+
+/// Telegram Mini App `initData` query string, decoded into its fields.
+///
+/// See <https://core.telegram.org/bots/webapps#validating-data-received-via-the-mini-app>.
+pub struct InitData {
+    fields: Map<String, String>,
+}
+
+impl InitData {
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let fields = form_urlencoded::parse(raw.trim().as_bytes())
+            .into_owned()
+            .collect::<Map<_, _>>();
+
+        if fields.is_empty() {
+            return Err("Init data is empty".to_owned());
+        }
+
+        Ok(Self { fields })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields.get(key).map(String::as_str)
+    }
+
+    pub fn hash(&self) -> Result<&str, String> {
+        self.get("hash")
+            .ok_or("Init data hash is missing".to_owned())
+    }
+
+    pub fn signature(&self) -> Result<&str, String> {
+        self.get("signature")
+            .ok_or("Init data signature is missing".to_owned())
+    }
+
+    /// Sorted `key=value` lines of every field except the `excluded` ones.
+    pub fn data_check_string(&self, excluded: &[&str]) -> String {
+        self.fields
+            .iter()
+            .filter(|(key, _)| !excluded.contains(&key.as_str()))
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Replay key of the proof: the fields it was verified on, without `hash` and `signature`,
+    /// and the decoded MAC or signature. Re-encoding the proof does not change it.
+    fn replay_key(&self, proof: &[u8]) -> Hash {
+        let data = self.data_check_string(&["hash", "signature"]);
+        let map = HashMap::from([
+            ("data", hash::Value::String(&data)),
+            ("proof", hash::Value::Bytes(proof)),
+        ]);
+
+        hash::domain(b"init_data", &hash::map(map))
+    }
+
+    /// Checks `hash` against `HMAC-SHA256(HMAC-SHA256("WebAppData", token), data_check_string)`
+    /// and returns the replay key of the proof.
+    pub fn verify(&self, token: &str) -> Result<Hash, String> {
+        let hash = hex::decode(self.hash()?).map_err(|_| "Init data hash is invalid".to_owned())?;
+
+        let mut mac = HmacSha256::new_from_slice(b"WebAppData").map_err(|e| e.to_string())?;
+        mac.update(token.as_bytes());
+        let secret = mac.finalize().into_bytes();
+
+        let mut mac = HmacSha256::new_from_slice(&secret).map_err(|e| e.to_string())?;
+        mac.update(self.data_check_string(&["hash"]).as_bytes());
+
+        mac.verify_slice(&hash)
+            .map_err(|_| "Init data hash mismatch".to_owned())?;
+
+        Ok(self.replay_key(&hash))
+    }
+
+    /// Checks `signature` against `"{bot}:WebAppData\n" + data_check_string` with any of the
+    /// given Telegram public keys and returns the replay key of the proof. The `hash` field is
+    /// not signed, so it is ignored.
+    pub fn verify_signature<'a>(
+        &self,
+        bot: u64,
+        keys: impl IntoIterator<Item = &'a [u8]>,
+    ) -> Result<Hash, String> {
+        let signature = BASE64_URL
+            .decode(self.signature()?)
+            .map_err(|_| "Init data signature is invalid".to_owned())?;
+        let signature = Ed25519Signature::from_slice(&signature)
+            .map_err(|_| "Init data signature is invalid".to_owned())?;
+        let message = format!(
+            "{bot}:WebAppData\n{}",
+            self.data_check_string(&["hash", "signature"])
+        );
+
+        for key in keys {
+            let Ok(key) = <[u8; 32]>::try_from(key) else {
+                continue;
+            };
+            let Ok(key) = VerifyingKey::from_bytes(&key) else {
+                continue;
+            };
+
+            if key.verify_strict(message.as_bytes(), &signature).is_ok() {
+                return Ok(self.replay_key(&signature.to_bytes()));
+            }
+        }
+
+        Err("Init data signature mismatch".to_owned())
+    }
+
+    pub fn user(&self) -> Result<User, String> {
+        let user = self
+            .get("user")
+            .ok_or("Init data user is missing".to_owned())?;
+
+        serde_json::from_str(user).map_err(|e| format!("Init data user is invalid: {}", e))
+    }
+
+    pub fn auth_date(&self) -> Result<u64, String> {
+        self.get("auth_date")
+            .ok_or("Init data auth date is missing".to_owned())?
+            .parse()
+            .map_err(|_| "Init data auth date is invalid".to_owned())
+    }
+}
+
+/// Telegram Login Widget callback data.
+///
+/// See <https://core.telegram.org/widgets/login#checking-authorization>.
+#[derive(CandidType, Clone, Deserialize)]
+pub struct Widget {
+    id: u64,
+    first_name: Option<String>,
+    last_name: Option<String>,
+    username: Option<String>,
+    photo_url: Option<String>,
+    auth_date: u64,
+    hash: String,
+}
+
+impl Widget {
+    /// Sorted `key=value` lines of every received field except `hash`.
+    pub fn data_check_string(&self) -> String {
+        let mut fields = Map::new();
+
+        fields.insert("id", self.id.to_string());
+        fields.insert("auth_date", self.auth_date.to_string());
+
+        let optionals = [
+            ("first_name", &self.first_name),
+            ("last_name", &self.last_name),
+            ("username", &self.username),
+            ("photo_url", &self.photo_url),
+        ];
+
+        for (key, value) in optionals {
+            if let Some(value) = value {
+                fields.insert(key, value.clone());
+            }
+        }
+
+        fields
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Checks `hash` against `HMAC-SHA256(SHA256(token), data_check_string)` and returns the
+    /// replay key of the proof: the verified fields and the decoded MAC.
+    pub fn verify(&self, token: &str) -> Result<Hash, String> {
+        let hash =
+            hex::decode(self.hash.trim()).map_err(|_| "Widget hash is invalid".to_owned())?;
+        let secret = Sha256::digest(token.as_bytes());
+
+        let mut mac = HmacSha256::new_from_slice(&secret).map_err(|e| e.to_string())?;
+        mac.update(self.data_check_string().as_bytes());
+
+        mac.verify_slice(&hash)
+            .map_err(|_| "Widget hash mismatch".to_owned())?;
+
+        let data = self.data_check_string();
+        let map = HashMap::from([
+            ("data", hash::Value::String(&data)),
+            ("proof", hash::Value::Bytes(&hash)),
+        ]);
+
+        Ok(hash::domain(b"widget", &hash::map(map)))
+    }
+
+    pub fn user(&self) -> User {
+        User {
+            id: self.id,
+            username: self.username.clone(),
+        }
+    }
+
+    pub fn auth_date(&self) -> u64 {
+        self.auth_date
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{DefaultMemoryImpl, MemoryId, MemoryManager, Proofs};
+
+    const TOKEN: &str = "123456:TEST-token";
+
+    /// `initData` signed with `TOKEN`, for user 42.
+    const INIT_DATA: &str = "auth_date=1700000000&query_id=AAE&user=%7B%22id%22%3A42%2C%22first_name%22%3A%22Ada%22%2C%22username%22%3A%22ada%22%7D&hash=00328b315b99a22085fcbe08d66e19393354789c7ff319bdcb777dc0c3d021eb";
+
+    const BOT: u64 = 123456;
+
+    fn signing_key() -> ed25519_dalek::SigningKey {
+        ed25519_dalek::SigningKey::from_bytes(&[7; 32])
+    }
+
+    /// `initData` for user 42 signed by a local key, with the signature in unpadded base64url.
+    fn signed_init_data() -> String {
+        use ed25519_dalek::Signer;
+
+        let user = r#"{"id":42,"first_name":"Ada"}"#;
+        let message = format!("{BOT}:WebAppData\nauth_date=1700000000\nuser={user}");
+        let signature = signing_key().sign(message.as_bytes());
+        let signature =
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(signature.to_bytes());
+
+        form_urlencoded::Serializer::new(String::new())
+            .append_pair("auth_date", "1700000000")
+            .append_pair("user", user)
+            .append_pair("signature", &signature)
+            .finish()
+    }
+
+    fn proofs() -> Proofs {
+        let manager = MemoryManager::init(DefaultMemoryImpl::default());
+
+        Proofs::init([manager.get(MemoryId::new(0)), manager.get(MemoryId::new(1))]).into_inner()
+    }
+
+    #[test]
+    fn recased_init_data_is_already_used() {
+        let recased = INIT_DATA.replace("00328b315b", "00328B315B");
+        let first = InitData::parse(INIT_DATA).unwrap().verify(TOKEN).unwrap();
+        let second = InitData::parse(&recased).unwrap().verify(TOKEN).unwrap();
+        let mut proofs = proofs();
+
+        assert_eq!(first, second);
+        assert!(proofs.insert(first, 1, 60, 1).is_ok());
+        assert_eq!(
+            proofs.insert(second, 1, 60, 1),
+            Err("Telegram proof has already been used".to_owned())
+        );
+    }
+
+    #[test]
+    fn repadded_signed_init_data_is_already_used() {
+        let key = signing_key().verifying_key().to_bytes();
+        let init_data = signed_init_data();
+        let repadded = format!("{}%3D%3D&hash=abcdef", init_data);
+        let first = InitData::parse(&init_data)
+            .unwrap()
+            .verify_signature(BOT, [&key[..]])
+            .unwrap();
+        let second = InitData::parse(&repadded)
+            .unwrap()
+            .verify_signature(BOT, [&key[..]])
+            .unwrap();
+        let mut proofs = proofs();
+
+        assert_eq!(first, second);
+        assert!(proofs.insert(first, 1, 60, 1).is_ok());
+        assert!(proofs.insert(second, 1, 60, 1).is_err());
+    }
+
+    #[test]
+    fn init_data_hash_is_verified_with_the_bot_token() {
+        let init_data = InitData::parse(INIT_DATA).unwrap();
+
+        assert!(init_data.verify(TOKEN).is_ok());
+        assert_eq!(init_data.user().unwrap().id(), 42);
+        assert_eq!(init_data.user().unwrap().username(), Some("ada"));
+        assert_eq!(init_data.auth_date().unwrap(), 1_700_000_000);
+        assert_eq!(
+            init_data.verify("123456:other-token"),
+            Err("Init data hash mismatch".to_owned())
+        );
+    }
+
+    #[test]
+    fn tampered_init_data_is_rejected() {
+        let tampered = INIT_DATA.replace("%3A42%2C", "%3A43%2C");
+
+        assert_eq!(
+            InitData::parse(&tampered).unwrap().verify(TOKEN),
+            Err("Init data hash mismatch".to_owned())
+        );
+    }
+
+    #[test]
+    fn init_data_signature_is_verified_with_telegram_keys() {
+        let key = signing_key().verifying_key().to_bytes();
+        let other = ed25519_dalek::SigningKey::from_bytes(&[8; 32])
+            .verifying_key()
+            .to_bytes();
+        let init_data = InitData::parse(&signed_init_data()).unwrap();
+
+        assert!(init_data.verify_signature(BOT, [&key[..]]).is_ok());
+        assert!(init_data
+            .verify_signature(BOT, [&other[..], &key[..]])
+            .is_ok());
+        assert_eq!(
+            init_data.verify_signature(BOT, [&other[..]]),
+            Err("Init data signature mismatch".to_owned())
+        );
+        assert_eq!(
+            init_data.verify_signature(BOT + 1, [&key[..]]),
+            Err("Init data signature mismatch".to_owned())
+        );
+    }
+
+    #[test]
+    fn tampered_signed_init_data_is_rejected() {
+        let key = signing_key().verifying_key().to_bytes();
+        let tampered = signed_init_data().replace("1700000000", "1700000001");
+
+        assert_eq!(
+            InitData::parse(&tampered)
+                .unwrap()
+                .verify_signature(BOT, [&key[..]]),
+            Err("Init data signature mismatch".to_owned())
+        );
+    }
+
+    fn widget(hash: &str) -> Widget {
+        Widget {
+            id: 42,
+            first_name: Some("Ada".to_owned()),
+            last_name: None,
+            username: Some("ada".to_owned()),
+            photo_url: None,
+            auth_date: 1_700_000_000,
+            hash: hash.to_owned(),
+        }
+    }
+
+    #[test]
+    fn widget_hash_is_verified_with_the_bot_token() {
+        let hash = "79060ff33fa31a240691763c967dd128d29a7051339b822584997833ac70e9b8";
+
+        assert_eq!(
+            widget(hash).data_check_string(),
+            "auth_date=1700000000\nfirst_name=Ada\nid=42\nusername=ada"
+        );
+        assert!(widget(hash).verify(TOKEN).is_ok());
+        assert_eq!(
+            widget(hash).verify("123456:other-token"),
+            Err("Widget hash mismatch".to_owned())
+        );
+        assert_eq!(
+            widget(&hash.to_uppercase()).verify(TOKEN),
+            widget(hash).verify(TOKEN)
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use crate::types::{CandidType, Deserialize, Map, Serialize};
+
+/// Template used when no template matches the message language.
+pub const DEFAULT_TEMPLATE: &str =
+    "User {user} want to create delegation for accessing canister(s): {canisters}";
+
+/// Placeholders a template may contain.
+pub const PLACEHOLDERS: [&str; 5] = ["user", "bot", "canisters", "expiration", "duration"];
+
+/// Maximum length of a template, in characters.
+const TEMPLATE_LENGTH: usize = 1024;
+
+/// Output format of a rendered consent message.
+#[derive(CandidType, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Plain,
+    /// Telegram `MarkdownV2`: literal text is escaped and values are bold.
+    Markdown,
+}
+
+/// Checks that a template is not empty, not too long, and only uses known placeholders.
+pub fn validate(template: &str) -> Result<(), String> {
+    if template.trim().is_empty() {
+        return Err("Template is empty".to_owned());
+    }
+
+    if template.chars().count() > TEMPLATE_LENGTH {
+        return Err(format!(
+            "Template is longer than {TEMPLATE_LENGTH} characters"
+        ));
+    }
+
+    for part in template.split('{').skip(1) {
+        let Some((name, _)) = part.split_once('}') else {
+            continue;
+        };
+
+        if !PLACEHOLDERS.contains(&name) {
+            return Err(format!("Template placeholder {{{name}}} is unknown"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Picks the template for a language code.
+///
+/// Falls back from a regional code like `pt-br` to `pt`, then to `en`, then to the built-in
+/// English template. Returns the language of the template found along with it.
+pub fn select<'a>(
+    templates: Option<&'a Map<String, String>>,
+    language: Option<&str>,
+) -> (&'a str, &'a str) {
+    let language = language.unwrap_or_default().to_lowercase();
+    let base = language.split(['-', '_']).next().unwrap_or_default();
+
+    let template = [language.as_str(), base, "en"]
+        .into_iter()
+        .find_map(|language| templates?.get_key_value(language));
+
+    template.map_or(("en", DEFAULT_TEMPLATE), |(language, template)| {
+        (language.as_str(), template.as_str())
+    })
+}
+
+/// Renders a template in one pass, so substituted values are never expanded again.
+///
+/// Placeholders are `{name}` with a name from `PLACEHOLDERS`; unknown ones are kept as is.
+pub fn render(template: &str, values: &[(&str, String)], format: Format) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let (literal, tail) = rest.split_at(start);
+
+        output.push_str(&escape(literal, format));
+
+        let value = tail[1..].split_once('}').and_then(|(name, _)| {
+            values
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| (name.len() + 2, value))
+        });
+
+        match value {
+            Some((length, value)) => {
+                match format {
+                    Format::Plain => output.push_str(value),
+                    Format::Markdown => {
+                        output.push('*');
+                        output.push_str(&escape(value, format));
+                        output.push('*');
+                    }
+                }
+
+                rest = &tail[length..];
+            }
+            None => {
+                output.push_str(&escape("{", format));
+                rest = &tail[1..];
+            }
+        }
+    }
+
+    output.push_str(&escape(rest, format));
+    output
+}
+
+/// Escapes the characters reserved by Telegram `MarkdownV2`.
+fn escape(text: &str, format: Format) -> String {
+    match format {
+        Format::Plain => text.to_owned(),
+        Format::Markdown => {
+            let mut escaped = String::with_capacity(text.len());
+
+            for c in text.chars() {
+                if "_*[]()~`>#+-=|{}.!\\".contains(c) {
+                    escaped.push('\\');
+                }
+
+                escaped.push(c);
+            }
+
+            escaped
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> [(&'static str, String); 2] {
+        [
+            ("user", "ada_lovelace".to_owned()),
+            ("bot", "{user}".to_owned()),
+        ]
+    }
+
+    #[test]
+    fn markdown_escapes_reserved_characters() {
+        let template = "Hi {user}! Sign in (1.5 min) via {bot}.";
+
+        assert_eq!(
+            render(template, &values(), Format::Markdown),
+            "Hi *ada\\_lovelace*\\! Sign in \\(1\\.5 min\\) via *\\{user\\}*\\."
+        );
+        assert_eq!(
+            render(template, &values(), Format::Plain),
+            "Hi ada_lovelace! Sign in (1.5 min) via {user}."
+        );
+    }
+
+    #[test]
+    fn unknown_placeholders_are_rejected_and_kept_as_is() {
+        assert_eq!(
+            validate("Hi {name}"),
+            Err("Template placeholder {name} is unknown".to_owned())
+        );
+        assert!(validate("Hi {user}, {unclosed").is_ok());
+        assert_eq!(
+            render("{name} {user}", &values(), Format::Plain),
+            "{name} ada_lovelace"
+        );
+        assert_eq!(
+            render("{name} {user}", &values(), Format::Markdown),
+            "\\{name\\} *ada\\_lovelace*"
+        );
+    }
+
+    #[test]
+    fn languages_fall_back_to_english_then_the_default() {
+        let templates = Map::from([
+            ("pt".to_owned(), "Olá {user}".to_owned()),
+            ("en".to_owned(), "Hello {user}".to_owned()),
+        ]);
+
+        assert_eq!(
+            select(Some(&templates), Some("pt-BR")),
+            ("pt", "Olá {user}")
+        );
+        assert_eq!(
+            select(Some(&templates), Some("pt_br")),
+            ("pt", "Olá {user}")
+        );
+        assert_eq!(select(Some(&templates), Some("de")), ("en", "Hello {user}"));
+        assert_eq!(select(Some(&templates), None), ("en", "Hello {user}"));
+        assert_eq!(select(None, Some("pt")), ("en", DEFAULT_TEMPLATE));
+    }
+}
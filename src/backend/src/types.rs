@@ -6,9 +6,12 @@ pub(crate) use std::collections::{BTreeMap as Map, BTreeSet as Set, BinaryHeap,
 pub(crate) use std::fmt;
 #[allow(unused)]
 pub(crate) use std::iter::{Cloned, Copied};
+pub(crate) use std::time::Duration;
 
-pub(crate) use candid::{CandidType, Principal};
+pub(crate) use candid::{CandidType, Nat, Principal};
 pub(crate) use chrono::{DateTime, Utc};
+pub(crate) use ed25519_dalek::{Signature as Ed25519Signature, VerifyingKey};
+pub(crate) use hmac::{Hmac, Mac};
 pub(crate) use ic_cdk::api::{data_certificate, set_certified_data};
 pub(crate) use ic_cdk::{caller as caller_principal, id as canister_principal};
 pub(crate) use ic_certified_map::{fork, fork_hash, labeled, labeled_hash, leaf_hash};
@@ -22,12 +25,20 @@ pub(crate) use sha2::{Digest, Sha256};
 pub(crate) use simple_asn1::{from_der, oid, to_der, ASN1Block};
 
 pub use crate::accounts::Accounts;
+pub use crate::application::Application;
+pub use crate::bot::Bot;
 pub use crate::delegation::Delegation;
+pub use crate::gc::Collection;
 pub use crate::globals::Globals;
 pub use crate::messages::{Message, Messages};
+pub use crate::proofs::Proofs;
+pub use crate::scope::Scope;
 pub use crate::setting::Setting;
-pub use crate::signatures::Signatures;
+pub use crate::signatures::{Signatures, Signed};
 pub use crate::state::{State, LABEL_ASSETS, LABEL_SIG};
+pub use crate::target::Target;
+pub use crate::telegram::{InitData, Proof, Verification};
+pub use crate::template::Format;
 pub use crate::timestamp::Timestamp;
 pub use crate::{hash, states, utils};
 
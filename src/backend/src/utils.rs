@@ -4,12 +4,17 @@ pub(crate) fn now() -> u64 {
     ic_cdk::api::time()
 }
 
+pub(crate) fn now_second() -> u64 {
+    now() / 1_000_000_000
+}
+
 pub(crate) fn now_add_minute(minute: u64) -> u64 {
     let minute = minute.saturating_mul(60).saturating_mul(1_000_000_000);
 
     now().saturating_add(minute)
 }
 
+/// Legacy unsalted seed, `sha256(hash(len) || user)`.
 pub(crate) fn seed(user: &str) -> Hash {
     let length = hash::u64(user.len() as u64);
     let mut seed = Vec::new();
@@ -19,3 +24,15 @@ pub(crate) fn seed(user: &str) -> Hash {
 
     hash::bytes(seed)
 }
+
+/// Seed mixed with the deployment salt, so principals cannot be derived from Telegram ids alone.
+pub(crate) fn salted_seed(salt: &[u8], user: &str) -> Hash {
+    let mut seed = Vec::new();
+
+    seed.extend(hash::u64(salt.len() as u64));
+    seed.extend(salt);
+    seed.extend(hash::u64(user.len() as u64));
+    seed.extend(user.as_bytes());
+
+    hash::domain(b"siwt-salted-seed", &seed)
+}